@@ -0,0 +1,110 @@
+use std::io;
+
+/// One more than the highest CPU a thread can be pinned to.
+#[cfg(target_os = "linux")]
+const MAX_CPUS: usize = libc::CPU_SETSIZE as usize;
+#[cfg(not(target_os = "linux"))]
+const MAX_CPUS: usize = 1024;
+
+/// Parses a CPU list in the same format as `taskset -c`, e.g. `0-3,6`. CPUs
+/// from `MAX_CPUS` on are rejected.
+pub fn parse_cpu_list(list: &str) -> Result<Vec<usize>, String> {
+    let mut cpus = Vec::new();
+    for part in list.split(',') {
+        let part = part.trim();
+        let invalid = |_| format!("invalid cpu '{}'", part);
+        let (lo, hi): (usize, usize) = match part.split_once('-') {
+            Some((lo, hi)) => (lo.parse().map_err(invalid)?, hi.parse().map_err(invalid)?),
+            None => {
+                let cpu = part.parse().map_err(invalid)?;
+                (cpu, cpu)
+            }
+        };
+        if lo > hi {
+            return Err(format!("invalid cpu range '{}'", part));
+        }
+        if hi >= MAX_CPUS {
+            return Err(format!("cpu {} is out of range", hi));
+        }
+        cpus.extend(lo..=hi);
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Ok(cpus)
+}
+
+/// Restricts the calling thread to `cpus`. Threads spawned afterwards inherit
+/// the mask, so calling this before building a scheduler pins all of its
+/// workers to the same set.
+#[cfg(target_os = "linux")]
+pub fn pin_current_thread(cpus: &[usize]) -> io::Result<()> {
+    // SAFETY: `cpu_set_t` is a plain bitmask, zeroed is the empty set, and
+    // `CPU_SET` is only called with indices below `CPU_SETSIZE`.
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for &cpu in cpus {
+            if cpu >= libc::CPU_SETSIZE as usize {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("cpu {} is out of range", cpu),
+                ));
+            }
+            libc::CPU_SET(cpu, &mut set);
+        }
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_current_thread(_cpus: &[usize]) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "cpu pinning is only supported on linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_cpus_and_ranges() {
+        assert_eq!(parse_cpu_list("3"), Ok(vec![3]));
+        assert_eq!(parse_cpu_list("0-3,6"), Ok(vec![0, 1, 2, 3, 6]));
+        assert_eq!(parse_cpu_list("2-2"), Ok(vec![2]));
+        assert_eq!(parse_cpu_list(" 1 , 4-5 "), Ok(vec![1, 4, 5]));
+    }
+
+    #[test]
+    fn sorted_without_duplicates() {
+        assert_eq!(parse_cpu_list("6,0-2,1,2-3"), Ok(vec![0, 1, 2, 3, 6]));
+    }
+
+    #[test]
+    fn bad_input() {
+        assert_eq!(parse_cpu_list(""), Err("invalid cpu ''".to_string()));
+        assert_eq!(parse_cpu_list("1,,2"), Err("invalid cpu ''".to_string()));
+        assert_eq!(parse_cpu_list("x"), Err("invalid cpu 'x'".to_string()));
+        assert_eq!(parse_cpu_list("-1"), Err("invalid cpu '-1'".to_string()));
+        assert_eq!(parse_cpu_list("1-"), Err("invalid cpu '1-'".to_string()));
+        assert_eq!(
+            parse_cpu_list("0-3-5"),
+            Err("invalid cpu '0-3-5'".to_string())
+        );
+        assert_eq!(
+            parse_cpu_list("4-1"),
+            Err("invalid cpu range '4-1'".to_string())
+        );
+        assert_eq!(
+            parse_cpu_list(&MAX_CPUS.to_string()),
+            Err(format!("cpu {} is out of range", MAX_CPUS))
+        );
+        assert_eq!(
+            parse_cpu_list("0-100000000000"),
+            Err("cpu 100000000000 is out of range".to_string())
+        );
+    }
+}
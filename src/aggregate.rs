@@ -0,0 +1,104 @@
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+use crate::{
+    scheduler::RunSummary,
+    task::{TaskType, MAX_KINDS},
+};
+
+/// The XOR of the outputs and the number of tasks of each type of some part
+/// of the tree, kept by a single worker without any synchronisation.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub output: u64,
+    /// Indexed by `TaskType::index`.
+    pub counts: [usize; MAX_KINDS],
+}
+
+impl Tally {
+    pub fn record(&mut self, typ: TaskType, output: u64) {
+        self.output ^= output;
+        self.counts[typ.index()] += 1;
+    }
+
+    pub fn merge(&mut self, other: &Tally) {
+        self.output ^= other.output;
+        for (count, other) in self.counts.iter_mut().zip(other.counts) {
+            *count += other;
+        }
+    }
+
+    pub fn summary(&self) -> RunSummary {
+        RunSummary {
+            output: self.output,
+            count_map: count_map(&self.counts),
+            ..Default::default()
+        }
+    }
+}
+
+fn count_map(counts: &[usize; MAX_KINDS]) -> HashMap<TaskType, usize> {
+    TaskType::all()
+        .into_iter()
+        .map(|typ| (typ, counts[typ.index()]))
+        .filter(|&(_, count)| count > 0)
+        .collect()
+}
+
+// A cache line of its own per worker, so counting never contends.
+#[derive(Default)]
+#[repr(align(64))]
+struct Slot([AtomicUsize; MAX_KINDS]);
+
+/// A `Tally` which any number of threads can record into at once, for
+/// schedulers whose workers do not report back to a central thread. Outputs
+/// are folded into a single atomic, counts into per-thread slots which are
+/// only added up at the end.
+pub struct Aggregator {
+    output: AtomicU64,
+    slots: Vec<Slot>,
+}
+
+impl Aggregator {
+    /// An aggregator for about `n_threads` threads. More threads still work,
+    /// they just share slots.
+    pub fn new(n_threads: usize) -> Self {
+        Aggregator {
+            output: AtomicU64::new(0),
+            slots: (0..n_threads.max(1)).map(|_| Slot::default()).collect(),
+        }
+    }
+
+    pub fn record(&self, typ: TaskType, output: u64) {
+        self.output.fetch_xor(output, Ordering::Relaxed);
+        let slot = &self.slots[thread_slot() % self.slots.len()];
+        slot.0[typ.index()].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Everything recorded so far. Only exact once the recording threads are
+    /// done, e.g. after joining them.
+    pub fn tally(&self) -> Tally {
+        let mut tally = Tally {
+            output: self.output.load(Ordering::Relaxed),
+            ..Default::default()
+        };
+        for slot in &self.slots {
+            for (count, slot_count) in tally.counts.iter_mut().zip(&slot.0) {
+                *count += slot_count.load(Ordering::Relaxed);
+            }
+        }
+        tally
+    }
+}
+
+// Threads are numbered in the order they first record something, so the
+// workers of one pool end up in different slots.
+fn thread_slot() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static SLOT: usize = NEXT.fetch_add(1, Ordering::Relaxed);
+    }
+    SLOT.with(|slot| *slot)
+}
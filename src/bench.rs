@@ -0,0 +1,150 @@
+use std::time::{Duration, Instant};
+
+use taskrunner::{scheduler::ThreadPoolChannel, task::Task, RunSummary, Scheduler, Strategy};
+
+use crate::cli::BenchArgs;
+
+/// Wall times of the repetitions of one strategy on one configuration.
+struct Sample {
+    name: String,
+    threads: usize,
+    times: Vec<Duration>,
+}
+
+impl Sample {
+    fn mean(&self) -> f64 {
+        self.times.iter().map(Duration::as_secs_f64).sum::<f64>() / self.times.len() as f64
+    }
+
+    fn stddev(&self) -> f64 {
+        let mean = self.mean();
+        let var = self
+            .times
+            .iter()
+            .map(|t| (t.as_secs_f64() - mean).powi(2))
+            .sum::<f64>()
+            / self.times.len() as f64;
+        var.sqrt()
+    }
+}
+
+fn measure(
+    name: String,
+    scheduler: &dyn Scheduler,
+    threads: usize,
+    initial: &[Task],
+    repetitions: usize,
+    expected: &mut Option<RunSummary>,
+    agree: &mut bool,
+) -> Sample {
+    let mut times = Vec::with_capacity(repetitions);
+    for _ in 0..repetitions {
+        let start = Instant::now();
+        let summary = scheduler.run(initial.to_vec());
+        times.push(start.elapsed());
+
+        match expected {
+            Some(expected) if expected.to_string() != summary.to_string() => {
+                eprintln!(
+                    "MISMATCH: {} with {} threads printed {}, expected {}",
+                    name, threads, summary, expected
+                );
+                *agree = false;
+            }
+            Some(_) => {}
+            None => *expected = Some(summary),
+        }
+    }
+    let sample = Sample {
+        name,
+        threads,
+        times,
+    };
+    eprintln!(
+        "  {:<28} {:>3} threads  {:.3} s",
+        sample.name,
+        threads,
+        sample.mean()
+    );
+    sample
+}
+
+/// Runs the whole matrix, prints the table on stdout and returns whether all
+/// strategies agreed on every configuration.
+pub fn run(bench: &BenchArgs) -> bool {
+    let mut agree = true;
+
+    println!(
+        "{:<22}{:>7}{:>9}{:>8}  {:<28}{:>10}{:>10}{:>9}",
+        "seed", "height", "children", "threads", "strategy", "mean s", "stddev s", "speedup"
+    );
+
+    for &seed in &bench.seeds {
+        for &height in &bench.heights {
+            for &max_children in &bench.max_children {
+                eprintln!(
+                    "seed {}, height {}, max. children {}",
+                    seed, height, max_children
+                );
+                let initial = Task::generate_initial(seed, height, max_children);
+                let mut expected = None;
+
+                // The serial baseline does not depend on the thread count.
+                let baseline = measure(
+                    Strategy::SerialDfs.name().to_string(),
+                    &*Strategy::SerialDfs.scheduler(1),
+                    1,
+                    &initial,
+                    bench.repetitions,
+                    &mut expected,
+                    &mut agree,
+                );
+                let mut samples = vec![];
+                for &threads in &bench.threads {
+                    for &strategy in bench
+                        .strategies
+                        .iter()
+                        .filter(|&&s| s != Strategy::SerialDfs)
+                    {
+                        samples.push(measure(
+                            strategy.name().to_string(),
+                            &*strategy.scheduler(threads),
+                            threads,
+                            &initial,
+                            bench.repetitions,
+                            &mut expected,
+                            &mut agree,
+                        ));
+                    }
+                    if bench.batch {
+                        samples.push(measure(
+                            format!("{} --batch", Strategy::ThreadPoolChannel.name()),
+                            &ThreadPoolChannel::new(threads).with_batching(),
+                            threads,
+                            &initial,
+                            bench.repetitions,
+                            &mut expected,
+                            &mut agree,
+                        ));
+                    }
+                }
+
+                for sample in std::iter::once(&baseline).chain(&samples) {
+                    println!(
+                        "{:<22}{:>7}{:>9}{:>8}  {:<28}{:>10.3}{:>10.3}{:>8.2}x",
+                        seed,
+                        height,
+                        max_children,
+                        sample.threads,
+                        sample.name,
+                        sample.mean(),
+                        sample.stddev(),
+                        baseline.mean() / sample.mean()
+                    );
+                }
+            }
+        }
+    }
+
+    agree
+}
@@ -0,0 +1,258 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    sync::{Mutex, RwLock},
+};
+
+use crate::task::{self, Task, TaskType};
+
+const MAGIC: &[u8; 4] = b"TRC2";
+// MAGIC fingerprint:u64, little endian
+const HEADER_LEN: usize = 12;
+// typ:u8 seed:u64 output:u64, little endian
+const RECORD_LEN: usize = 17;
+
+/// The output of a task body only depends on its type and seed, so it can be
+/// remembered across runs. The cache is an append-only file of fixed size
+/// records which is read into memory when it is opened.
+///
+/// Records refer to the type by its index in the registry, so the file starts
+/// with the `task::fingerprint` of the kinds registered when it was created,
+/// and it can only be opened with the same kinds registered.
+pub struct OutputCache {
+    path: PathBuf,
+    outputs: RwLock<HashMap<(TaskType, u64), u64>>,
+    file: Mutex<BufWriter<File>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+/// How often `get_or_compute` found an output in the cache.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl CacheStats {
+    /// The lookups that happened between `earlier` and `self`.
+    pub fn since(self, earlier: CacheStats) -> CacheStats {
+        CacheStats {
+            hits: self.hits - earlier.hits,
+            misses: self.misses - earlier.misses,
+        }
+    }
+}
+
+/// Where the cache lives unless told otherwise: `$TASKRUNNER_CACHE`, else
+/// `$XDG_CACHE_HOME/taskrunner/outputs`, else `~/.cache/taskrunner/outputs`.
+pub fn default_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("TASKRUNNER_CACHE") {
+        return Some(PathBuf::from(path));
+    }
+    let dir = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(dir.join("taskrunner").join("outputs"))
+}
+
+fn encode(typ: TaskType, seed: u64, output: u64) -> [u8; RECORD_LEN] {
+    let mut record = [0; RECORD_LEN];
+    record[0] = typ.index() as u8;
+    record[1..9].copy_from_slice(&seed.to_le_bytes());
+    record[9..17].copy_from_slice(&output.to_le_bytes());
+    record
+}
+
+fn decode(record: &[u8]) -> io::Result<((TaskType, u64), u64)> {
+    let typ = TaskType::from_index(record[0] as usize).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("record of unknown task type {}", record[0]),
+        )
+    })?;
+    let seed = u64::from_le_bytes(record[1..9].try_into().unwrap());
+    let output = u64::from_le_bytes(record[9..17].try_into().unwrap());
+    Ok(((typ, seed), output))
+}
+
+fn header() -> [u8; HEADER_LEN] {
+    let mut header = [0; HEADER_LEN];
+    header[..4].copy_from_slice(MAGIC);
+    header[4..].copy_from_slice(&task::fingerprint().to_le_bytes());
+    header
+}
+
+type Outputs = HashMap<(TaskType, u64), u64>;
+
+/// The outputs in the non-empty `contents` of a cache file, and the length of
+/// its complete records.
+fn parse(contents: &[u8]) -> io::Result<(Outputs, usize)> {
+    if contents.len() < HEADER_LEN || &contents[..MAGIC.len()] != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a taskrunner cache",
+        ));
+    }
+    if contents[..HEADER_LEN] != header() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "cache was created with other task kinds registered",
+        ));
+    }
+    let records = &contents[HEADER_LEN..];
+    let complete = records.len() - records.len() % RECORD_LEN;
+    let mut outputs = HashMap::new();
+    for record in records[..complete].chunks_exact(RECORD_LEN) {
+        let (key, output) = decode(record)?;
+        outputs.insert(key, output);
+    }
+    Ok((outputs, complete))
+}
+
+impl OutputCache {
+    /// Opens the cache at `path`, creating it if it does not exist yet. A
+    /// record that was only partially written (e.g. by a crash) is dropped.
+    /// Fails if the cache was created with other task kinds registered.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<OutputCache> {
+        let path = path.into();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        let mut outputs = HashMap::new();
+        if contents.is_empty() {
+            file.write_all(&header())?;
+        } else {
+            let complete;
+            (outputs, complete) = parse(&contents)?;
+            if HEADER_LEN + complete != contents.len() {
+                file.set_len((HEADER_LEN + complete) as u64)?;
+                file.seek(SeekFrom::End(0))?;
+            }
+        }
+
+        Ok(OutputCache {
+            path,
+            outputs: RwLock::new(outputs),
+            file: Mutex::new(BufWriter::new(file)),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        })
+    }
+
+    /// The number of outputs in the cache at `path`, which has to exist. The
+    /// file is only read.
+    pub fn count(path: &Path) -> io::Result<usize> {
+        let contents = fs::read(path)?;
+        if contents.is_empty() {
+            return Ok(0);
+        }
+        Ok(parse(&contents)?.0.len())
+    }
+
+    /// Rewrites the cache at `path` with a single record per task, dropping
+    /// the duplicates several processes appending at once may leave behind.
+    /// Returns how many records were kept.
+    pub fn compact(path: &Path) -> io::Result<usize> {
+        let cache = OutputCache::open(path)?;
+        let outputs = cache.outputs.into_inner().unwrap();
+        // next to the cache, whatever its extension
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        {
+            let mut out = BufWriter::new(File::create(&tmp)?);
+            out.write_all(&header())?;
+            for (&(typ, seed), &output) in &outputs {
+                out.write_all(&encode(typ, seed, output))?;
+            }
+            out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        }
+        fs::rename(tmp, path)?;
+        Ok(outputs.len())
+    }
+
+    /// Drops every record of the cache at `path`, which then belongs to the
+    /// task kinds registered now.
+    pub fn clear(path: &Path) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        file.write_all(&header())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> usize {
+        self.outputs.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, task: &Task) -> Option<u64> {
+        self.outputs
+            .read()
+            .unwrap()
+            .get(&(task.typ, task.seed))
+            .copied()
+    }
+
+    pub fn insert(&self, task: &Task, output: u64) -> io::Result<()> {
+        if self
+            .outputs
+            .write()
+            .unwrap()
+            .insert((task.typ, task.seed), output)
+            .is_some()
+        {
+            return Ok(());
+        }
+        self.file
+            .lock()
+            .unwrap()
+            .write_all(&encode(task.typ, task.seed, output))
+    }
+
+    /// The output of `task` from the cache, or else from `compute`, which is
+    /// then added to the cache. Failing to write the cache only loses the
+    /// record, not the output.
+    pub fn get_or_compute(&self, task: &Task, compute: impl FnOnce() -> u64) -> u64 {
+        if let Some(output) = self.get(task) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return output;
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let output = compute();
+        if let Err(e) = self.insert(task, output) {
+            eprintln!(
+                "warning: failed to write to cache {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+        output
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        self.file.lock().unwrap().flush()
+    }
+}
@@ -0,0 +1,35 @@
+use std::io;
+
+use taskrunner::cache::OutputCache;
+
+use crate::cli::{CacheAction, CacheArgs};
+
+pub fn run(args: &CacheArgs) -> io::Result<()> {
+    let path = args.path.as_path();
+    match args.action {
+        CacheAction::Info => {
+            // Only looking, so a mistyped path must not create a cache.
+            let outputs = OutputCache::count(path).map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => {
+                    io::Error::new(io::ErrorKind::NotFound, "no cache at this path")
+                }
+                _ => e,
+            })?;
+            println!(
+                "{}: {} outputs, {} bytes",
+                path.display(),
+                outputs,
+                std::fs::metadata(path)?.len()
+            );
+        }
+        CacheAction::Compact => {
+            let kept = OutputCache::compact(path)?;
+            eprintln!("Compacted {} to {} outputs", path.display(), kept);
+        }
+        CacheAction::Clear => {
+            OutputCache::clear(path)?;
+            eprintln!("Cleared {}", path.display());
+        }
+    }
+    Ok(())
+}
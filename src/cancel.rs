@@ -0,0 +1,88 @@
+use std::{
+    io,
+    sync::atomic::{AtomicBool, Ordering},
+    sync::Arc,
+    time::Instant,
+};
+
+/// Asks a running scheduler to stop early, either explicitly, on a signal or
+/// once a deadline has passed. Clones share the same state.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Cancels this token on SIGINT or SIGTERM. A second signal exits the
+    /// process right away, without waiting for the scheduler. Only one token
+    /// per process can be hooked up to the signals.
+    #[cfg(target_os = "linux")]
+    pub fn cancel_on_signals(&self) -> io::Result<()> {
+        if SIGNALLED.set(self.cancelled.clone()).is_err() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "signal handlers are already installed",
+            ));
+        }
+        for signal in [libc::SIGINT, libc::SIGTERM] {
+            // SAFETY: `on_signal` only performs async-signal-safe operations.
+            if unsafe {
+                libc::signal(
+                    signal,
+                    on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+                )
+            } == libc::SIG_ERR
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn cancel_on_signals(&self) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "signal handling is only supported on linux",
+        ))
+    }
+}
+
+#[cfg(target_os = "linux")]
+static SIGNALLED: std::sync::OnceLock<Arc<AtomicBool>> = std::sync::OnceLock::new();
+
+#[cfg(target_os = "linux")]
+extern "C" fn on_signal(_signal: libc::c_int) {
+    const MESSAGE: &[u8] = b"\nStopping early, signal again to abort right away\n";
+    let Some(cancelled) = SIGNALLED.get() else {
+        return;
+    };
+    // SAFETY: `write` and `_exit` are async-signal-safe.
+    unsafe {
+        if cancelled.swap(true, Ordering::SeqCst) {
+            libc::_exit(130);
+        }
+        libc::write(libc::STDERR_FILENO, MESSAGE.as_ptr().cast(), MESSAGE.len());
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    task::{self, Task, TaskType},
+    RunSummary,
+};
+
+const MAGIC: &str = "taskrunner-checkpoint 2";
+
+/// A snapshot of a partially executed tree: the XOR and counts of every task
+/// that has completed, and the tasks that still have to run (queued or in
+/// flight when the snapshot was taken). Running `pending` to completion and
+/// merging the result into `output`/`count_map` gives the same final line as
+/// an uninterrupted run.
+///
+/// Stored as plain text, one header field per line followed by one line per
+/// pending task. The `task::fingerprint` of the registered kinds is part of
+/// the header, since the rest of the tree depends on them, and a checkpoint
+/// is only read back with the same kinds registered.
+///
+/// ```text
+/// taskrunner-checkpoint 2
+/// fingerprint 6169085691306298785
+/// seed 5664168989938163334
+/// starting_height 5
+/// max_children 5
+/// output 1234
+/// counts 10 12 9
+/// pending 2
+/// hash 98765 3 5
+/// random 4321 2 5
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub seed: u64,
+    pub starting_height: usize,
+    pub max_children: usize,
+    pub output: u64,
+    pub count_map: HashMap<TaskType, usize>,
+    pub pending: Vec<Task>,
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn parse_type(name: &str) -> io::Result<TaskType> {
+    TaskType::all()
+        .into_iter()
+        .find(|typ| typ.name() == name)
+        .ok_or_else(|| invalid(format!("unknown task type '{}'", name)))
+}
+
+fn parse_num<T: std::str::FromStr>(value: Option<&str>, what: &str) -> io::Result<T> {
+    value
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| invalid(format!("invalid {}", what)))
+}
+
+impl Checkpoint {
+    /// The output and counts of the completed part of the tree.
+    pub fn summary(&self) -> RunSummary {
+        RunSummary {
+            output: self.output,
+            count_map: self.count_map.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Writes the checkpoint next to `path` first and then renames it over,
+    /// so a crash while writing never leaves a truncated file behind.
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        // next to the checkpoint, whatever its extension
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        {
+            let mut out = BufWriter::new(fs::File::create(&tmp)?);
+            writeln!(out, "{}", MAGIC)?;
+            writeln!(out, "fingerprint {}", task::fingerprint())?;
+            writeln!(out, "seed {}", self.seed)?;
+            writeln!(out, "starting_height {}", self.starting_height)?;
+            writeln!(out, "max_children {}", self.max_children)?;
+            writeln!(out, "output {}", self.output)?;
+            // one count per registered type, in registry order
+            let counts: Vec<_> = TaskType::all()
+                .into_iter()
+                .map(|typ| self.count_map.get(&typ).unwrap_or(&0).to_string())
+                .collect();
+            writeln!(out, "counts {}", counts.join(" "))?;
+            writeln!(out, "pending {}", self.pending.len())?;
+            for task in &self.pending {
+                writeln!(
+                    out,
+                    "{} {} {} {}",
+                    task.typ.name(),
+                    task.seed,
+                    task.height,
+                    task.max_children
+                )?;
+            }
+            out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        }
+        fs::rename(tmp, path)
+    }
+
+    pub fn read_from(path: &Path) -> io::Result<Checkpoint> {
+        let file = io::BufReader::new(fs::File::open(path)?);
+        let mut lines = file.lines();
+        let mut next_line = || {
+            lines
+                .next()
+                .unwrap_or_else(|| Err(invalid("unexpected end of checkpoint")))
+        };
+
+        if next_line()? != MAGIC {
+            return Err(invalid("not a taskrunner checkpoint"));
+        }
+
+        let mut field = |name: &str| -> io::Result<String> {
+            let line = next_line()?;
+            match line.split_once(' ') {
+                Some((key, value)) if key == name => Ok(value.to_string()),
+                _ => Err(invalid(format!("expected '{}'", name))),
+            }
+        };
+
+        let fingerprint: u64 = parse_num(Some(&field("fingerprint")?), "fingerprint")?;
+        if fingerprint != task::fingerprint() {
+            return Err(invalid(
+                "checkpoint was written with other task kinds registered",
+            ));
+        }
+        let seed = parse_num(Some(&field("seed")?), "seed")?;
+        let starting_height = parse_num(Some(&field("starting_height")?), "starting_height")?;
+        let max_children = parse_num(Some(&field("max_children")?), "max_children")?;
+        let output = parse_num(Some(&field("output")?), "output")?;
+        let counts = field("counts")?;
+        let mut count_map = HashMap::new();
+        for (index, count) in counts.split(' ').enumerate() {
+            let typ = TaskType::from_index(index)
+                .ok_or_else(|| invalid("counts for an unknown task type"))?;
+            count_map.insert(typ, parse_num(Some(count), "counts")?);
+        }
+        let n_pending: usize = parse_num(Some(&field("pending")?), "pending")?;
+
+        let mut pending = Vec::with_capacity(n_pending);
+        for _ in 0..n_pending {
+            let line = next_line()?;
+            let mut parts = line.split(' ');
+            pending.push(Task {
+                typ: parse_type(parts.next().unwrap_or(""))?,
+                seed: parse_num(parts.next(), "task seed")?,
+                height: parse_num(parts.next(), "task height")?,
+                max_children: parse_num(parts.next(), "task max_children")?,
+            });
+        }
+
+        Ok(Checkpoint {
+            seed,
+            starting_height,
+            max_children,
+            output,
+            count_map,
+            pending,
+        })
+    }
+}
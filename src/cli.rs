@@ -0,0 +1,866 @@
+use std::{fmt, path::PathBuf, str::FromStr, time::Duration};
+
+use taskrunner::{
+    affinity, cache,
+    scheduler::{Policy, POLICIES, STRATEGIES},
+    Strategy,
+};
+
+/// Everything needed to run one task tree.
+#[derive(Clone, Debug)]
+pub struct Args {
+    pub seed: u64,
+    pub starting_height: usize,
+    pub max_children: usize,
+    pub threads: usize,
+    pub cpus: Option<Vec<usize>>,
+    pub strategy: Strategy,
+    pub output_format: OutputFormat,
+    pub timings: bool,
+    pub checkpoint: Option<PathBuf>,
+    pub checkpoint_interval: Duration,
+    pub resume: Option<PathBuf>,
+    /// Serve the tasks to remote workers on this address instead of running
+    /// them in process.
+    pub listen: Option<String>,
+    pub max_pending: Option<usize>,
+    pub policy: Option<Policy>,
+    /// Stop dispatching tasks once the run has taken this long.
+    pub deadline: Option<Duration>,
+    /// Print a progress line on stderr this often.
+    pub progress: Option<Duration>,
+    /// How often a panicking task is run again before the run fails.
+    pub retries: usize,
+    /// Hand the tasks to the pool in batches instead of one at a time.
+    pub batch: bool,
+    /// Look the task outputs up in this `OutputCache` before running them.
+    pub cache: Option<PathBuf>,
+}
+
+impl Args {
+    /// What actually runs the tree, for reporting.
+    pub fn strategy_name(&self) -> &'static str {
+        if self.listen.is_some() {
+            "coordinator"
+        } else {
+            self.strategy.name()
+        }
+    }
+}
+
+/// How the run summary is printed on stdout.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The original `output,hash,derive,random` line.
+    #[default]
+    Csv,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err("expected one of: csv, json".to_string()),
+        }
+    }
+}
+
+/// The matrix of runs performed by `taskrunner bench`.
+#[derive(Clone, Debug)]
+pub struct BenchArgs {
+    pub seeds: Vec<u64>,
+    pub heights: Vec<usize>,
+    pub max_children: Vec<usize>,
+    pub threads: Vec<usize>,
+    pub strategies: Vec<Strategy>,
+    pub repetitions: usize,
+    /// Also runs threadpool-channel with `--batch`.
+    pub batch: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TreeFormat {
+    Dot,
+    Json,
+}
+
+impl FromStr for TreeFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(TreeFormat::Dot),
+            "json" => Ok(TreeFormat::Json),
+            _ => Err("expected one of: dot, json".to_string()),
+        }
+    }
+}
+
+/// Options of `taskrunner export-tree`.
+#[derive(Clone, Debug)]
+pub struct ExportArgs {
+    pub seed: u64,
+    pub starting_height: usize,
+    pub max_children: usize,
+    pub threads: usize,
+    pub format: TreeFormat,
+    pub max_depth: Option<usize>,
+    /// Where to write the tree, stdout if `None`.
+    pub output: Option<PathBuf>,
+}
+
+/// Options of `taskrunner stats`.
+#[derive(Clone, Debug)]
+pub struct StatsArgs {
+    pub seed: u64,
+    pub starting_height: usize,
+    pub max_children: usize,
+    pub threads: usize,
+    /// `None` with `--no-cache`.
+    pub cache: Option<PathBuf>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CacheAction {
+    Info,
+    Compact,
+    Clear,
+}
+
+/// Options of `taskrunner cache`.
+#[derive(Clone, Debug)]
+pub struct CacheArgs {
+    pub path: PathBuf,
+    pub action: CacheAction,
+}
+
+#[derive(Debug)]
+pub enum Command {
+    Run(Args),
+    Bench(BenchArgs),
+    ExportTree(ExportArgs),
+    Stats(StatsArgs),
+    Cache(CacheArgs),
+    Worker { connect: String, threads: usize },
+    Help,
+    Version,
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    MissingValue(String),
+    InvalidValue {
+        flag: String,
+        value: String,
+        reason: String,
+    },
+    UnknownArgument(String),
+    Conflict(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::MissingValue(flag) => write!(f, "missing value for {}", flag),
+            CliError::InvalidValue {
+                flag,
+                value,
+                reason,
+            } => {
+                write!(f, "invalid value '{}' for {}: {}", value, flag, reason)
+            }
+            CliError::UnknownArgument(arg) => write!(f, "unexpected argument '{}'", arg),
+            CliError::Conflict(msg) => f.write_str(msg),
+        }
+    }
+}
+
+const DEFAULT_ADDR: &str = "127.0.0.1:7878";
+
+// The seeds `test-all.sh` has always been run with.
+const DEFAULT_BENCH_SEEDS: [u64; 3] = [
+    5664168989938163334,
+    1976915708242608314,
+    12605174704058567923,
+];
+
+pub fn usage() -> String {
+    let strategies: Vec<_> = STRATEGIES.iter().map(|s| s.name()).collect();
+    let policies: Vec<_> = POLICIES.iter().map(|p| p.name()).collect();
+    format!(
+        "\
+Usage: taskrunner [OPTIONS] [SEED] [HEIGHT] [MAX_CHILDREN]
+       taskrunner coordinator [--listen <addr>] [OPTIONS] [SEED] [HEIGHT] [MAX_CHILDREN]
+       taskrunner worker [--connect <addr>] [--threads <usize>]
+       taskrunner bench [--seeds <list>] [--heights <list>] [--max-children <list>]
+                        [--threads <list>] [--strategies <list>] [--repetitions <usize>]
+                        [--batch]
+       taskrunner export-tree [--format dot|json] [--max-depth <usize>] [-o <file>]
+                              [--seed <u64>] [--height <usize>] [--max-children <usize>]
+                              [--threads <usize>]
+       taskrunner stats [--seed <u64>] [--height <usize>] [--max-children <usize>]
+                        [--threads <usize>] [--cache <file> | --no-cache]
+       taskrunner cache [--cache <file>] [info|compact|clear]
+
+Options:
+  -s, --seed <u64>            seed of the initial task set [default: random]
+  -H, --height <usize>        starting height of the task tree [default: 5]
+  -c, --max-children <usize>  max. children of every task [default: 5]
+  -t, --threads <usize>       number of worker threads [default: $TASKRUNNER_THREADS,
+                              else the size of --cpus, else the number of CPUs]
+      --cpus <list>           pin the workers to these CPUs, e.g. 0-3,6
+      --strategy <name>       scheduler to run [default: {}]
+                              one of: {}
+      --output-format <fmt>   csv or json [default: csv]
+      --timings               print per-type task latencies on stderr
+      --checkpoint <file>     periodically save progress to this file
+                              (only with the threadpool-channel strategy)
+      --checkpoint-interval <secs>
+                              seconds between checkpoints [default: 30]
+      --max-pending <usize>   switch to depth first expansion before more than this
+                              many tasks are queued or running at once
+                              (only with the threadpool-channel strategy)
+      --policy <name>         order in which ready tasks are run, one of:
+                              {}
+                              (only with the threadpool-channel strategy)
+      --cache <file>          reuse the task outputs stored in this cache file and
+                              add the missing ones (only with the threadpool-channel
+                              strategy)
+      --deadline <secs>       stop the run after this many seconds
+                              (only with the threadpool-channel strategy)
+      --progress <secs>       print the completed tasks per type, the queued and
+                              running tasks, the current depth and the throughput
+                              on stderr this often
+                              (only with the threadpool-channel strategy)
+      --retries <usize>       run a task which panics again up to this many times
+                              before giving up [default: 0]
+                              (only with the threadpool-channel strategy)
+      --batch                 dispatch the tasks in batches sized after the queue,
+                              which helps when the tasks are cheap, e.g. cached
+                              (only with the threadpool-channel strategy)
+      --resume <file>         continue the run saved in this checkpoint, its seed,
+                              height and max. children replace the given ones
+  -h, --help                  print this help
+  -V, --version               print the version
+
+The seed, height and max. children may also be given positionally.
+
+With the threadpool-channel strategy, SIGINT, SIGTERM or the deadline stop the
+run: no more tasks are started, the running ones are waited for (or left
+pending in the checkpoint) and the result of the tasks that did run is printed
+followed by ',incomplete'. The exit status is then 1. A second signal aborts
+right away. A task which still panics after --retries stops the run the same
+way, and is reported with its type, seed and height.
+
+The coordinator serves the task tree to workers over TCP instead of running it
+itself, and prints the same result once the tree is done. Each worker opens
+--threads connections to it. Both default to {}. When all workers have been
+gone for 30 seconds the coordinator stops, like on a deadline.
+
+bench runs every strategy over the given comma separated lists, checks that
+they all agree and prints the mean wall time of each and its speedup over the
+serial-dfs baseline. It defaults to the seeds {}, height 3,
+max. children 5, the number of CPUs and 3 repetitions. With --batch
+threadpool-channel also runs with --batch, next to its default mode.
+
+export-tree executes the tree and writes every task with its parent, type,
+seed, height and output as a Graphviz graph (colored by type) or as JSON.
+Tasks deeper than --max-depth below the initial set (depth 0) are left out.
+
+stats prints the number of tasks per type and level, the widest level and the
+total rounds of the task bodies. The outputs of the bodies are kept in a cache
+file, so only the first query for a tree has to execute them. The cache
+defaults to $TASKRUNNER_CACHE, else $XDG_CACHE_HOME/taskrunner/outputs.
+
+cache prints the number of outputs in that cache (info, the default), rewrites
+it without duplicate records (compact) or removes all of them (clear).
+",
+        Strategy::default(),
+        strategies.join(", "),
+        policies.join(", "),
+        DEFAULT_ADDR,
+        DEFAULT_BENCH_SEEDS.map(|seed| seed.to_string()).join(",")
+    )
+}
+
+fn parse_positive(flag: &str, value: String) -> Result<usize, CliError> {
+    let n: usize = parse_value(flag, value)?;
+    if n == 0 {
+        return Err(CliError::InvalidValue {
+            flag: flag.to_string(),
+            value: n.to_string(),
+            reason: "must be at least 1".to_string(),
+        });
+    }
+    Ok(n)
+}
+
+fn parse_secs(flag: &str, value: String) -> Result<Duration, CliError> {
+    let secs: f64 = parse_value(flag, value.clone())?;
+    Duration::try_from_secs_f64(secs).map_err(|e| CliError::InvalidValue {
+        flag: flag.to_string(),
+        value,
+        reason: e.to_string(),
+    })
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: String) -> Result<T, CliError>
+where
+    T::Err: fmt::Display,
+{
+    value.parse().map_err(|e: T::Err| CliError::InvalidValue {
+        flag: flag.to_string(),
+        reason: e.to_string(),
+        value,
+    })
+}
+
+// Accept both `--flag value` and `--flag=value`.
+fn split_flag(arg: &str) -> (String, Option<String>) {
+    match arg.split_once('=') {
+        Some((flag, value)) if arg.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+        _ => (arg.to_string(), None),
+    }
+}
+
+fn resolve_threads(threads: Option<usize>, cpus: Option<&Vec<usize>>) -> Result<usize, CliError> {
+    if let Some(threads) = threads {
+        return Ok(threads);
+    }
+    if let Ok(value) = std::env::var("TASKRUNNER_THREADS") {
+        return parse_positive("TASKRUNNER_THREADS", value);
+    }
+    Ok(cpus.map(|cpus| cpus.len()).unwrap_or_else(num_cpus::get))
+}
+
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, CliError> {
+    let mut args = args.into_iter().peekable();
+    match args.peek().map(String::as_str) {
+        Some("worker") => {
+            args.next();
+            parse_worker(args)
+        }
+        Some("coordinator") => {
+            args.next();
+            parse_run(args, true)
+        }
+        Some("bench") => {
+            args.next();
+            parse_bench(args)
+        }
+        Some("export-tree") => {
+            args.next();
+            parse_export(args)
+        }
+        Some("stats") => {
+            args.next();
+            parse_stats(args)
+        }
+        Some("cache") => {
+            args.next();
+            parse_cache(args)
+        }
+        _ => parse_run(args, false),
+    }
+}
+
+fn parse_list<T: FromStr>(flag: &str, value: String) -> Result<Vec<T>, CliError>
+where
+    T::Err: fmt::Display,
+{
+    value
+        .split(',')
+        .map(|item| parse_value(flag, item.trim().to_string()))
+        .collect()
+}
+
+fn parse_bench(mut args: impl Iterator<Item = String>) -> Result<Command, CliError> {
+    let mut bench = BenchArgs {
+        seeds: DEFAULT_BENCH_SEEDS.to_vec(),
+        heights: vec![3],
+        max_children: vec![5],
+        threads: vec![num_cpus::get()],
+        strategies: STRATEGIES.to_vec(),
+        repetitions: 3,
+        batch: false,
+    };
+
+    while let Some(arg) = args.next() {
+        let (flag, inline) = split_flag(&arg);
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| CliError::MissingValue(flag.clone()))
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--seeds" => bench.seeds = parse_list(&flag, value()?)?,
+            "--heights" => bench.heights = parse_list(&flag, value()?)?,
+            "--max-children" => bench.max_children = parse_list(&flag, value()?)?,
+            "--threads" => {
+                bench.threads = value()?
+                    .split(',')
+                    .map(|n| parse_positive(&flag, n.trim().to_string()))
+                    .collect::<Result<_, _>>()?
+            }
+            "--strategies" => bench.strategies = parse_list(&flag, value()?)?,
+            "--repetitions" => bench.repetitions = parse_positive(&flag, value()?)?,
+            "--batch" => bench.batch = true,
+            _ => return Err(CliError::UnknownArgument(arg)),
+        }
+    }
+
+    Ok(Command::Bench(bench))
+}
+
+fn parse_export(mut args: impl Iterator<Item = String>) -> Result<Command, CliError> {
+    let mut seed = None;
+    let mut starting_height = 5;
+    let mut max_children = 5;
+    let mut threads = None;
+    let mut format = TreeFormat::Dot;
+    let mut max_depth = None;
+    let mut output = None;
+
+    while let Some(arg) = args.next() {
+        let (flag, inline) = split_flag(&arg);
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| CliError::MissingValue(flag.clone()))
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-s" | "--seed" => seed = Some(parse_value(&flag, value()?)?),
+            "-H" | "--height" => starting_height = parse_value(&flag, value()?)?,
+            "-c" | "--max-children" => max_children = parse_value(&flag, value()?)?,
+            "-t" | "--threads" => threads = Some(parse_positive(&flag, value()?)?),
+            "--format" => format = parse_value(&flag, value()?)?,
+            "--max-depth" => max_depth = Some(parse_value(&flag, value()?)?),
+            "-o" | "--output" => output = Some(PathBuf::from(value()?)),
+            _ => return Err(CliError::UnknownArgument(arg)),
+        }
+    }
+
+    Ok(Command::ExportTree(ExportArgs {
+        seed: seed.unwrap_or_else(|| rand::Rng::gen(&mut rand::thread_rng())),
+        starting_height,
+        max_children,
+        threads: resolve_threads(threads, None)?,
+        format,
+        max_depth,
+        output,
+    }))
+}
+
+fn parse_stats(mut args: impl Iterator<Item = String>) -> Result<Command, CliError> {
+    let mut seed = None;
+    let mut starting_height = 5;
+    let mut max_children = 5;
+    let mut threads = None;
+    let mut cache = cache::default_path();
+
+    while let Some(arg) = args.next() {
+        let (flag, inline) = split_flag(&arg);
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| CliError::MissingValue(flag.clone()))
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-s" | "--seed" => seed = Some(parse_value(&flag, value()?)?),
+            "-H" | "--height" => starting_height = parse_value(&flag, value()?)?,
+            "-c" | "--max-children" => max_children = parse_value(&flag, value()?)?,
+            "-t" | "--threads" => threads = Some(parse_positive(&flag, value()?)?),
+            "--cache" => cache = Some(PathBuf::from(value()?)),
+            "--no-cache" => cache = None,
+            _ => return Err(CliError::UnknownArgument(arg)),
+        }
+    }
+
+    Ok(Command::Stats(StatsArgs {
+        seed: seed.unwrap_or_else(|| rand::Rng::gen(&mut rand::thread_rng())),
+        starting_height,
+        max_children,
+        threads: resolve_threads(threads, None)?,
+        cache,
+    }))
+}
+
+fn parse_cache(mut args: impl Iterator<Item = String>) -> Result<Command, CliError> {
+    let mut path = cache::default_path();
+    let mut action = None;
+
+    while let Some(arg) = args.next() {
+        let (flag, inline) = split_flag(&arg);
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| CliError::MissingValue(flag.clone()))
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--cache" => path = Some(PathBuf::from(value()?)),
+            "info" | "compact" | "clear" if action.is_none() => {
+                action = Some(match flag.as_str() {
+                    "info" => CacheAction::Info,
+                    "compact" => CacheAction::Compact,
+                    _ => CacheAction::Clear,
+                })
+            }
+            _ => return Err(CliError::UnknownArgument(arg)),
+        }
+    }
+
+    let path = path.ok_or_else(|| CliError::MissingValue("--cache".to_string()))?;
+    Ok(Command::Cache(CacheArgs {
+        path,
+        action: action.unwrap_or(CacheAction::Info),
+    }))
+}
+
+fn parse_worker(mut args: impl Iterator<Item = String>) -> Result<Command, CliError> {
+    let mut connect = DEFAULT_ADDR.to_string();
+    let mut threads = None;
+
+    while let Some(arg) = args.next() {
+        let (flag, inline) = split_flag(&arg);
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| CliError::MissingValue(flag.clone()))
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "--connect" => connect = value()?,
+            "-t" | "--threads" => threads = Some(parse_positive(&flag, value()?)?),
+            _ => return Err(CliError::UnknownArgument(arg)),
+        }
+    }
+
+    Ok(Command::Worker {
+        connect,
+        threads: resolve_threads(threads, None)?,
+    })
+}
+
+fn parse_run(
+    mut args: impl Iterator<Item = String>,
+    coordinator: bool,
+) -> Result<Command, CliError> {
+    let mut seed = None;
+    let mut starting_height = None;
+    let mut max_children = None;
+    let mut threads = None;
+    let mut cpus: Option<Vec<usize>> = None;
+    let mut strategy = Strategy::default();
+    let mut output_format = OutputFormat::default();
+    let mut timings = false;
+    let mut strategy_given = false;
+    let mut checkpoint = None;
+    let mut checkpoint_interval = Duration::from_secs(30);
+    let mut resume = None;
+    let mut max_pending = None;
+    let mut policy = None;
+    let mut deadline = None;
+    let mut progress = None;
+    let mut retries = None;
+    let mut batch = false;
+    let mut cache = None;
+    let mut listen = coordinator.then(|| DEFAULT_ADDR.to_string());
+    let mut positional = 0;
+
+    while let Some(arg) = args.next() {
+        let (flag, inline) = split_flag(&arg);
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| CliError::MissingValue(flag.clone()))
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "-s" | "--seed" => seed = Some(parse_value(&flag, value()?)?),
+            "-H" | "--height" => starting_height = Some(parse_value(&flag, value()?)?),
+            "-c" | "--max-children" => max_children = Some(parse_value(&flag, value()?)?),
+            "-t" | "--threads" => threads = Some(parse_positive(&flag, value()?)?),
+            "--cpus" => {
+                let list = value()?;
+                cpus = Some(affinity::parse_cpu_list(&list).map_err(|reason| {
+                    CliError::InvalidValue {
+                        flag: flag.clone(),
+                        value: list,
+                        reason,
+                    }
+                })?);
+            }
+            "--strategy" => {
+                strategy = parse_value(&flag, value()?)?;
+                strategy_given = true;
+            }
+            "--output-format" => output_format = parse_value(&flag, value()?)?,
+            "--timings" => timings = true,
+            "--checkpoint" => checkpoint = Some(PathBuf::from(value()?)),
+            "--checkpoint-interval" => checkpoint_interval = parse_secs(&flag, value()?)?,
+            "--resume" => resume = Some(PathBuf::from(value()?)),
+            "--listen" if coordinator => listen = Some(value()?),
+            "--max-pending" => max_pending = Some(parse_positive(&flag, value()?)?),
+            "--policy" => policy = Some(parse_value(&flag, value()?)?),
+            "--deadline" => deadline = Some(parse_secs(&flag, value()?)?),
+            "--progress" => {
+                let value = value()?;
+                let interval = parse_secs(&flag, value.clone())?;
+                if interval.is_zero() {
+                    return Err(CliError::InvalidValue {
+                        flag: flag.clone(),
+                        value,
+                        reason: "must be more than 0".to_string(),
+                    });
+                }
+                progress = Some(interval);
+            }
+            "--retries" => retries = Some(parse_value(&flag, value()?)?),
+            "--batch" => batch = true,
+            "--cache" => cache = Some(PathBuf::from(value()?)),
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(CliError::UnknownArgument(arg))
+            }
+            _ => {
+                match positional {
+                    0 => seed = Some(parse_value("SEED", arg)?),
+                    1 => starting_height = Some(parse_value("HEIGHT", arg)?),
+                    2 => max_children = Some(parse_value("MAX_CHILDREN", arg)?),
+                    _ => return Err(CliError::UnknownArgument(arg)),
+                }
+                positional += 1;
+            }
+        }
+    }
+
+    // Options which only the main threadpool-channel scheduler supports.
+    let main_only = [
+        ("--checkpoint", checkpoint.is_some()),
+        ("--max-pending", max_pending.is_some()),
+        ("--policy", policy.is_some()),
+        ("--cache", cache.is_some()),
+        ("--deadline", deadline.is_some()),
+        ("--progress", progress.is_some()),
+        ("--retries", retries.is_some()),
+        ("--batch", batch),
+    ];
+    for (option, _) in main_only.iter().filter(|(_, given)| *given) {
+        if strategy_given && strategy != Strategy::ThreadPoolChannel {
+            return Err(CliError::InvalidValue {
+                flag: "--strategy".to_string(),
+                value: strategy.to_string(),
+                reason: format!(
+                    "{} is only supported by {}",
+                    option,
+                    Strategy::ThreadPoolChannel
+                ),
+            });
+        }
+        if coordinator {
+            return Err(CliError::Conflict(format!(
+                "{} cannot be used by the coordinator",
+                option
+            )));
+        }
+    }
+    // Each of these is a different way of running the main scheduler.
+    let modes = [
+        ("--checkpoint", checkpoint.is_some()),
+        ("--max-pending", max_pending.is_some()),
+        ("--policy", policy.is_some()),
+        ("--batch", batch),
+    ];
+    let given: Vec<_> = modes
+        .iter()
+        .filter(|(_, given)| *given)
+        .map(|(option, _)| *option)
+        .collect();
+    if given.len() > 1 {
+        return Err(CliError::Conflict(format!(
+            "{} cannot be combined",
+            given.join(" and ")
+        )));
+    }
+    // Options the checkpointing scheduler does not support.
+    let unsupported = [
+        ("--cache", cache.is_some()),
+        ("--progress", progress.is_some()),
+        ("--retries", retries.is_some()),
+    ];
+    for (option, given) in unsupported {
+        if given && checkpoint.is_some() {
+            return Err(CliError::Conflict(format!(
+                "--checkpoint and {} cannot be combined",
+                option
+            )));
+        }
+    }
+
+    let threads = resolve_threads(threads, cpus.as_ref())?;
+
+    Ok(Command::Run(Args {
+        seed: seed.unwrap_or_else(|| rand::Rng::gen(&mut rand::thread_rng())),
+        starting_height: starting_height.unwrap_or(5),
+        max_children: max_children.unwrap_or(5),
+        threads,
+        cpus,
+        strategy,
+        output_format,
+        timings,
+        checkpoint,
+        checkpoint_interval,
+        resume,
+        listen,
+        max_pending,
+        policy,
+        deadline,
+        progress,
+        retries: retries.unwrap_or(0),
+        batch,
+        cache,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every option that only the threadpool-channel scheduler understands,
+    // with a valid value.
+    const MAIN_ONLY: &[&str] = &[
+        "--checkpoint run.checkpoint",
+        "--max-pending 8",
+        "--policy lifo",
+        "--cache outputs",
+        "--deadline 1",
+        "--progress 1",
+        "--retries 1",
+        "--batch",
+    ];
+
+    fn parse_str(args: &str) -> Result<Command, CliError> {
+        parse(args.split_whitespace().map(String::from))
+    }
+
+    fn conflict(args: &str) -> String {
+        match parse_str(args) {
+            Err(CliError::Conflict(message)) => message,
+            other => panic!("expected a conflict for '{}', got {:?}", args, other),
+        }
+    }
+
+    #[test]
+    fn main_only_options_are_accepted_alone() {
+        for option in MAIN_ONLY {
+            assert!(
+                matches!(parse_str(option), Ok(Command::Run(_))),
+                "{}",
+                option
+            );
+            let args = format!("--strategy threadpool-channel {}", option);
+            assert!(matches!(parse_str(&args), Ok(Command::Run(_))), "{}", args);
+        }
+    }
+
+    #[test]
+    fn main_only_options_need_threadpool_channel() {
+        for option in MAIN_ONLY {
+            let args = format!("--strategy serial-dfs {}", option);
+            match parse_str(&args) {
+                Err(CliError::InvalidValue { flag, reason, .. }) => {
+                    assert_eq!(flag, "--strategy");
+                    assert!(
+                        reason.starts_with(option.split(' ').next().unwrap()),
+                        "{}",
+                        reason
+                    );
+                }
+                other => panic!("expected '{}' to be rejected, got {:?}", args, other),
+            }
+        }
+    }
+
+    #[test]
+    fn main_only_options_are_rejected_by_the_coordinator() {
+        for option in MAIN_ONLY {
+            let flag = option.split(' ').next().unwrap();
+            assert_eq!(
+                conflict(&format!("coordinator {}", option)),
+                format!("{} cannot be used by the coordinator", flag)
+            );
+        }
+    }
+
+    #[test]
+    fn modes_are_mutually_exclusive() {
+        let modes = [
+            "--checkpoint run.checkpoint",
+            "--max-pending 8",
+            "--policy lifo",
+            "--batch",
+        ];
+        for (i, first) in modes.iter().enumerate() {
+            for second in &modes[i + 1..] {
+                let flags = |option: &str| option.split(' ').next().unwrap().to_string();
+                assert_eq!(
+                    conflict(&format!("{} {}", first, second)),
+                    format!("{} and {} cannot be combined", flags(first), flags(second))
+                );
+            }
+        }
+        assert_eq!(
+            conflict("--policy lifo --batch --max-pending 8"),
+            "--max-pending and --policy and --batch cannot be combined"
+        );
+    }
+
+    #[test]
+    fn checkpoint_rejects_unsupported_options() {
+        for option in ["--cache outputs", "--progress 1", "--retries 1"] {
+            let flag = option.split(' ').next().unwrap();
+            assert_eq!(
+                conflict(&format!("--checkpoint run.checkpoint {}", option)),
+                format!("--checkpoint and {} cannot be combined", flag)
+            );
+        }
+    }
+
+    #[test]
+    fn progress_interval_must_be_positive() {
+        assert!(matches!(
+            parse_str("--progress 0"),
+            Err(CliError::InvalidValue { flag, .. }) if flag == "--progress"
+        ));
+    }
+
+    #[test]
+    fn bench_batch_adds_a_variant() {
+        assert!(matches!(parse_str("bench"), Ok(Command::Bench(bench)) if !bench.batch));
+        assert!(matches!(
+            parse_str("bench --threads 4 --batch"),
+            Ok(Command::Bench(bench)) if bench.batch && bench.threads == [4]
+        ));
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, BufReader, BufWriter, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use super::protocol::{self, DONE, GET, KINDS_DIFFER, RESULT, TASK};
+use crate::{
+    stats::TimingRecorder,
+    task::{Task, TaskType},
+    RunSummary, Scheduler,
+};
+
+/// A `Scheduler` which does not execute anything itself, but serves the
+/// tasks to workers connecting over TCP (see `run_worker`).
+///
+/// A task that was handed out to a connection which then drops is put back
+/// at the front of the queue, so workers may come and go during a run. If
+/// all of them are gone for longer than the worker timeout, the run stops
+/// and the summary counts the tasks that were left as unfinished.
+pub struct Coordinator {
+    listener: TcpListener,
+    worker_timeout: Duration,
+}
+
+/// How long a run waits for a new worker after the last one disconnected.
+const DEFAULT_WORKER_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Default)]
+struct State {
+    taskq: VecDeque<Task>,
+    in_flight: usize,
+    output: u64,
+    count_map: HashMap<TaskType, usize>,
+}
+
+impl State {
+    fn finished(&self) -> bool {
+        self.taskq.is_empty() && self.in_flight == 0
+    }
+}
+
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+    recorder: TimingRecorder,
+}
+
+impl Coordinator {
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Coordinator {
+            listener,
+            worker_timeout: DEFAULT_WORKER_TIMEOUT,
+        })
+    }
+
+    pub fn with_worker_timeout(mut self, timeout: Duration) -> Self {
+        self.worker_timeout = timeout;
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+impl Shared {
+    /// Blocks until there is a task to hand out, or returns `None` once the
+    /// whole tree is done.
+    fn take(&self) -> Option<Task> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(task) = state.taskq.pop_front() {
+                state.in_flight += 1;
+                return Some(task);
+            }
+            if state.finished() {
+                return None;
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+
+    fn complete(&self, task: &Task, output: u64, elapsed: Duration, children: Vec<Task>) {
+        self.recorder.record(task.typ, elapsed);
+        let mut state = self.state.lock().unwrap();
+        state.in_flight -= 1;
+        state.output ^= output;
+        *state.count_map.entry(task.typ).or_insert(0usize) += 1;
+        state.taskq.extend(children);
+        self.changed.notify_all();
+    }
+
+    fn requeue(&self, task: Task) {
+        let mut state = self.state.lock().unwrap();
+        state.in_flight -= 1;
+        state.taskq.push_front(task);
+        self.changed.notify_all();
+    }
+
+    fn serve(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = BufWriter::new(stream);
+
+        if !protocol::read_hello(&mut reader)? {
+            writer.write_all(&[KINDS_DIFFER])?;
+            writer.flush()?;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "worker has other task kinds registered",
+            ));
+        }
+
+        loop {
+            if protocol::read_u8(&mut reader)? != GET {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "expected a task request",
+                ));
+            }
+
+            let Some(task) = self.take() else {
+                writer.write_all(&[DONE])?;
+                return writer.flush();
+            };
+
+            let result = (|| {
+                writer.write_all(&[TASK])?;
+                protocol::write_task(&mut writer, &task)?;
+                writer.flush()?;
+                if protocol::read_u8(&mut reader)? != RESULT {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "expected a result",
+                    ));
+                }
+                protocol::read_result(&mut reader)
+            })();
+
+            match result {
+                Ok((output, elapsed, children)) => self.complete(&task, output, elapsed, children),
+                Err(e) => {
+                    self.requeue(task);
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+impl Scheduler for Coordinator {
+    fn run(&self, initial: Vec<Task>) -> RunSummary {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                taskq: VecDeque::from(initial),
+                ..Default::default()
+            }),
+            changed: Condvar::new(),
+            recorder: TimingRecorder::new(),
+        });
+
+        let mut connections: Vec<JoinHandle<()>> = Vec::new();
+        // since when no worker has been connected, once one has been
+        let mut abandoned: Option<Instant> = None;
+        let mut unfinished = None;
+
+        // The listener is non-blocking so that we notice when the tree is done
+        // even if no worker ever connects again.
+        while !shared.state.lock().unwrap().finished() {
+            match self.listener.accept() {
+                Ok((stream, peer)) => {
+                    let shared = shared.clone();
+                    connections.push(thread::spawn(move || {
+                        if let Err(e) = shared.serve(stream) {
+                            eprintln!("worker {} disconnected: {}", peer, e);
+                        }
+                    }));
+                    abandoned = None;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(10))
+                }
+                Err(e) => eprintln!("failed to accept worker: {}", e),
+            }
+
+            let had_connections = !connections.is_empty();
+            connections.retain(|connection| !connection.is_finished());
+            if had_connections && connections.is_empty() {
+                abandoned = Some(Instant::now());
+            }
+            if abandoned.is_some_and(|since| since.elapsed() >= self.worker_timeout) {
+                let queued = shared.state.lock().unwrap().taskq.len();
+                eprintln!(
+                    "no workers for {} s, stopping with {} tasks left",
+                    self.worker_timeout.as_secs_f64(),
+                    queued
+                );
+                unfinished = Some(queued);
+                break;
+            }
+        }
+
+        // Connections still have to tell their workers that the tree is done.
+        for connection in connections {
+            connection.join().unwrap();
+        }
+
+        let state = shared.state.lock().unwrap();
+        RunSummary {
+            output: state.output,
+            count_map: state.count_map.clone(),
+            timings: shared.recorder.summarize(),
+            unfinished,
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,10 @@
+//! Runs a task tree across several processes. A `Coordinator` owns the
+//! frontier and hands out one task at a time to every connected worker
+//! connection, see `protocol` for the wire format.
+
+mod coordinator;
+mod protocol;
+mod worker;
+
+pub use coordinator::Coordinator;
+pub use worker::run_worker;
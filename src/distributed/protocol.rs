@@ -0,0 +1,125 @@
+//! Every connection starts with the worker sending `MAGIC` and the
+//! `task::fingerprint` of its registered kinds, after which the worker drives
+//! the conversation. Task types are sent by their index in the registry, so a
+//! worker with other kinds is turned away. All integers are big endian.
+//!
+//! ```text
+//! worker -> coordinator
+//!   'G'                                      give me a task
+//!   'R' output:u64 nanos:u64 n:u32 task*n    result of the last task
+//!
+//! coordinator -> worker
+//!   'T' task                                 execute this task
+//!   'D'                                      the tree is done, disconnect
+//!   'K'                                      other task kinds, disconnect
+//!
+//! task = typ:u8 seed:u64 height:u64 max_children:u64
+//! ```
+
+use std::{
+    io::{self, Read, Write},
+    time::Duration,
+};
+
+use crate::task::{self, Task, TaskType};
+
+pub const MAGIC: &[u8; 4] = b"TRN2";
+
+pub const GET: u8 = b'G';
+pub const RESULT: u8 = b'R';
+pub const TASK: u8 = b'T';
+pub const DONE: u8 = b'D';
+pub const KINDS_DIFFER: u8 = b'K';
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+pub fn read_u8(r: &mut impl Read) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+pub fn write_hello(w: &mut impl Write) -> io::Result<()> {
+    w.write_all(MAGIC)?;
+    w.write_all(&task::fingerprint().to_be_bytes())
+}
+
+/// Reads the start of a connection and returns whether the worker has the
+/// same task kinds registered.
+pub fn read_hello(r: &mut impl Read) -> io::Result<bool> {
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a taskrunner worker"));
+    }
+    Ok(read_u64(r)? == task::fingerprint())
+}
+
+pub fn write_task(w: &mut impl Write, task: &Task) -> io::Result<()> {
+    w.write_all(&[task.typ.index() as u8])?;
+    w.write_all(&task.seed.to_be_bytes())?;
+    w.write_all(&(task.height as u64).to_be_bytes())?;
+    w.write_all(&(task.max_children as u64).to_be_bytes())
+}
+
+pub fn read_task(r: &mut impl Read) -> io::Result<Task> {
+    let typ =
+        TaskType::from_index(read_u8(r)? as usize).ok_or_else(|| invalid("unknown task type"))?;
+    let seed = read_u64(r)?;
+    let height = read_u64(r)?
+        .try_into()
+        .map_err(|_| invalid("height out of range"))?;
+    let max_children = read_u64(r)?
+        .try_into()
+        .map_err(|_| invalid("max_children out of range"))?;
+    Ok(Task {
+        typ,
+        seed,
+        height,
+        max_children,
+    })
+}
+
+pub fn write_result(
+    w: &mut impl Write,
+    output: u64,
+    elapsed: Duration,
+    children: &[Task],
+) -> io::Result<()> {
+    let nanos = elapsed.as_nanos().min(u64::MAX as u128) as u64;
+    let n: u32 = children
+        .len()
+        .try_into()
+        .map_err(|_| invalid("too many children"))?;
+    w.write_all(&[RESULT])?;
+    w.write_all(&output.to_be_bytes())?;
+    w.write_all(&nanos.to_be_bytes())?;
+    w.write_all(&n.to_be_bytes())?;
+    for child in children {
+        write_task(w, child)?;
+    }
+    Ok(())
+}
+
+/// Reads the body of a `RESULT` message, after its opcode.
+pub fn read_result(r: &mut impl Read) -> io::Result<(u64, Duration, Vec<Task>)> {
+    let output = read_u64(r)?;
+    let elapsed = Duration::from_nanos(read_u64(r)?);
+    let n = read_u32(r)?;
+    let children = (0..n).map(|_| read_task(r)).collect::<io::Result<_>>()?;
+    Ok((output, elapsed, children))
+}
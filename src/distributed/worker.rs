@@ -0,0 +1,78 @@
+use std::{
+    io::{self, BufReader, BufWriter, Write},
+    net::{TcpStream, ToSocketAddrs},
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::protocol::{self, DONE, GET, KINDS_DIFFER, TASK};
+
+// Workers may be started before the coordinator is listening.
+const CONNECT_ATTEMPTS: usize = 50;
+const CONNECT_BACKOFF: Duration = Duration::from_millis(100);
+
+fn connect(addr: &(impl ToSocketAddrs + ?Sized)) -> io::Result<TcpStream> {
+    let mut attempt = 0;
+    loop {
+        match TcpStream::connect(addr) {
+            Ok(stream) => return Ok(stream),
+            Err(e)
+                if attempt + 1 < CONNECT_ATTEMPTS
+                    && e.kind() == io::ErrorKind::ConnectionRefused =>
+            {
+                attempt += 1;
+                thread::sleep(CONNECT_BACKOFF);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn work(addr: &(impl ToSocketAddrs + ?Sized)) -> io::Result<usize> {
+    let stream = connect(addr)?;
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    protocol::write_hello(&mut writer)?;
+    let mut executed = 0;
+    loop {
+        writer.write_all(&[GET])?;
+        writer.flush()?;
+        match protocol::read_u8(&mut reader)? {
+            TASK => {
+                let task = protocol::read_task(&mut reader)?;
+                let start = Instant::now();
+                let (output, children) = task.execute();
+                protocol::write_result(&mut writer, output, start.elapsed(), &children)?;
+                executed += 1;
+            }
+            DONE => return Ok(executed),
+            KINDS_DIFFER => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "coordinator has other task kinds registered",
+                ))
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "unexpected message from coordinator",
+                ))
+            }
+        }
+    }
+}
+
+/// Opens `n_threads` connections to the coordinator at `addr` and executes
+/// tasks on each until the coordinator reports that the tree is done.
+/// Returns how many tasks this process executed.
+pub fn run_worker(
+    addr: &(impl ToSocketAddrs + Sync + ?Sized),
+    n_threads: usize,
+) -> io::Result<usize> {
+    thread::scope(|s| {
+        let handles: Vec<_> = (0..n_threads).map(|_| s.spawn(|| work(addr))).collect();
+        handles.into_iter().map(|h| h.join().unwrap()).sum()
+    })
+}
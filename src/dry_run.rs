@@ -0,0 +1,93 @@
+use std::{io, time::Instant};
+
+use taskrunner::{
+    cache::OutputCache,
+    task::{Task, TaskType},
+    tree,
+};
+
+use crate::cli::StatsArgs;
+
+pub fn run(args: &StatsArgs) -> io::Result<()> {
+    eprintln!(
+        "Statistics for seed {}, starting height {}, max. children {}",
+        args.seed, args.starting_height, args.max_children
+    );
+
+    let cache = args.cache.as_ref().map(OutputCache::open).transpose()?;
+    if let Some(cache) = &cache {
+        eprintln!(
+            "Using cache {} with {} outputs",
+            cache.path().display(),
+            cache.len()
+        );
+    }
+
+    let start = Instant::now();
+    let initial = Task::generate_initial(args.seed, args.starting_height, args.max_children);
+    let stats = tree::statistics(initial, args.threads, cache.as_ref())?;
+    eprintln!(
+        "Completed in {} s, {} outputs from the cache, {} executed",
+        start.elapsed().as_secs_f64(),
+        stats.cached,
+        stats.executed
+    );
+
+    let types = TaskType::all();
+    let columns = |values: Vec<String>| {
+        values
+            .iter()
+            .map(|value| format!("{:>10}", value))
+            .collect::<String>()
+    };
+
+    let names = types.iter().map(|typ| typ.name().to_string()).collect();
+    println!(
+        "{:>6}{:>8}{}{:>10}",
+        "level",
+        "height",
+        columns(names),
+        "total"
+    );
+    for (depth, level) in stats.levels.iter().enumerate() {
+        let counts = types
+            .iter()
+            .map(|typ| level.counts[typ.index()].to_string())
+            .collect();
+        println!(
+            "{:>6}{:>8}{}{:>10}",
+            depth,
+            level.height,
+            columns(counts),
+            level.counts.iter().sum::<usize>()
+        );
+    }
+    let total: usize = stats
+        .levels
+        .iter()
+        .map(|level| level.counts.iter().sum::<usize>())
+        .sum();
+    let counts = types
+        .iter()
+        .map(|typ| stats.count(*typ).to_string())
+        .collect();
+    println!("{:>14}{}{:>10}", "total", columns(counts), total);
+    let rounds = types
+        .iter()
+        .map(|typ| stats.rounds[typ.index()].to_string())
+        .collect();
+    println!(
+        "{:>14}{}{:>10}",
+        "rounds",
+        columns(rounds),
+        stats.rounds.iter().sum::<u64>()
+    );
+    println!();
+    println!("max. frontier width {}", stats.max_frontier);
+    let counts: Vec<_> = types
+        .iter()
+        .map(|typ| stats.count(*typ).to_string())
+        .collect();
+    println!("output {},{}", stats.output, counts.join(","));
+    Ok(())
+}
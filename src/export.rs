@@ -0,0 +1,41 @@
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+};
+
+use taskrunner::{task::Task, tree};
+
+use crate::cli::{ExportArgs, TreeFormat};
+
+pub fn run(args: &ExportArgs) -> io::Result<()> {
+    eprintln!(
+        "Exporting seed {}, starting height {}, max. children {}, max. depth {}",
+        args.seed,
+        args.starting_height,
+        args.max_children,
+        args.max_depth
+            .map_or_else(|| "unlimited".to_string(), |d| d.to_string())
+    );
+
+    let initial = Task::generate_initial(args.seed, args.starting_height, args.max_children);
+    let nodes = tree::explore(initial, args.threads, args.max_depth);
+
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(fs::File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    match args.format {
+        TreeFormat::Dot => tree::write_dot(&nodes, args.seed, &mut out)?,
+        TreeFormat::Json => tree::write_json(
+            &nodes,
+            args.seed,
+            args.starting_height,
+            args.max_children,
+            &mut out,
+        )?,
+    }
+    out.flush()?;
+
+    eprintln!("Exported {} tasks", nodes.len());
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use crate::task::{Children, Task};
+
+pub type LazyTaskResult = (
+    u64,
+    Option<LazyTask>, // child
+    Option<LazyTask>, // sibling
+);
+
+/// A `Task` which produces its set of children one at a time instead of all
+/// at once, so only the first child and the next sibling ever exist in memory.
+///
+/// Each task carries the rest of the set it was drawn from, and is the one
+/// responsible for drawing the next sibling once it has been executed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LazyTask {
+    pub task: Task,
+    pub siblings: Children,
+}
+
+impl LazyTask {
+    /// Takes the first task of `set`, which then carries the rest of it.
+    pub fn first_of(mut set: Children) -> Option<LazyTask> {
+        set.next().map(|task| LazyTask {
+            task,
+            siblings: set,
+        })
+    }
+
+    pub fn execute(&self) -> LazyTaskResult {
+        let output = self.task.compute();
+        let (child_task, sibling_task) = self.get_next(output);
+        (output, child_task, sibling_task)
+    }
+
+    /// The first child of this task and its own next sibling, given the
+    /// output of its body.
+    pub fn get_next(&self, output: u64) -> (Option<LazyTask>, Option<LazyTask>) {
+        (
+            LazyTask::first_of(self.task.children(output)),
+            self.sibling(),
+        )
+    }
+
+    /// The next task of the set this task belongs to, if any.
+    pub fn sibling(&self) -> Option<LazyTask> {
+        LazyTask::first_of(self.siblings.clone())
+    }
+
+    pub fn generate_initial(
+        seed: u64,
+        starting_height: usize,
+        max_children: usize,
+    ) -> Option<LazyTask> {
+        LazyTask::first_of(Task::initial_children(seed, starting_height, max_children))
+    }
+}
+
+// An already materialised task becomes a set of one, which only generates
+// its children lazily.
+impl From<Task> for LazyTask {
+    fn from(task: Task) -> Self {
+        LazyTask {
+            task,
+            siblings: Children::empty(),
+        }
+    }
+}
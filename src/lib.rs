@@ -0,0 +1,14 @@
+pub mod affinity;
+pub mod aggregate;
+pub mod cache;
+pub mod cancel;
+pub mod checkpoint;
+pub mod distributed;
+pub mod lazy;
+pub mod progress;
+pub mod scheduler;
+pub mod stats;
+pub mod task;
+pub mod tree;
+
+pub use scheduler::{RunSummary, Scheduler, Strategy};
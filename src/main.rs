@@ -1,91 +1,264 @@
-use std::{
-    collections::{HashMap, VecDeque},
-    time::Instant,
-    sync::mpsc::channel,
-    sync::mpsc::Sender,
-    sync::mpsc::Receiver,
-};
+use std::{process, sync::Arc, time::Instant};
 
-use task::{Task, TaskType};
-use threadpool::ThreadPool;
-use num_cpus;
+use taskrunner::{
+    affinity,
+    cache::OutputCache,
+    cancel::CancelToken,
+    checkpoint::Checkpoint,
+    distributed::{self, Coordinator},
+    scheduler::{Checkpointing, ThreadPoolChannel},
+    stats,
+    task::Task,
+    Scheduler, Strategy,
+};
 
-fn execute_task(send: &Sender<(u64, Vec<Task>)>, count_map: &mut HashMap<TaskType, usize>, spawned: &mut u64, pool: &ThreadPool, next: Task) {
-    let send = send.clone();
-    *count_map.entry(next.typ).or_insert(0usize) += 1;
-    *spawned += 1;
-    pool.execute(move || {
-        send.send(next.execute()).unwrap();
-    });
-}
+use cli::{CacheAction, Command};
+use report::Report;
 
-fn wait_task(recv: &Receiver<(u64, Vec<Task>)>, spawned: &mut u64, output: &mut u64) -> std::vec::IntoIter<Task> {
-    let result = recv.recv().unwrap();
-    *spawned -= 1;
-    *output ^= result.0;
-    result.1.into_iter()
-}
+mod bench;
+mod cache_admin;
+mod cli;
+mod dry_run;
+mod export;
+mod report;
 
 fn main() {
-    let (seed, starting_height, max_children) = get_args();
+    let mut args = match cli::parse(std::env::args().skip(1)) {
+        Ok(Command::Run(args)) => args,
+        Ok(Command::Bench(bench_args)) => {
+            if !bench::run(&bench_args) {
+                eprintln!("error: the strategies did not agree on the output");
+                process::exit(1);
+            }
+            return;
+        }
+        Ok(Command::ExportTree(export_args)) => {
+            if let Err(e) = export::run(&export_args) {
+                eprintln!("error: failed to export the tree: {}", e);
+                process::exit(1);
+            }
+            return;
+        }
+        Ok(Command::Stats(stats_args)) => {
+            if let Err(e) = dry_run::run(&stats_args) {
+                eprintln!("error: failed to compute the tree statistics: {}", e);
+                process::exit(1);
+            }
+            return;
+        }
+        Ok(Command::Cache(cache_args)) => {
+            if let Err(e) = cache_admin::run(&cache_args) {
+                let verb = match cache_args.action {
+                    CacheAction::Info => "read",
+                    CacheAction::Compact | CacheAction::Clear => "update",
+                };
+                eprintln!(
+                    "error: failed to {} cache {}: {}",
+                    verb,
+                    cache_args.path.display(),
+                    e
+                );
+                process::exit(1);
+            }
+            return;
+        }
+        Ok(Command::Worker { connect, threads }) => {
+            eprintln!("Connecting {} worker threads to {}", threads, connect);
+            match distributed::run_worker(connect.as_str(), threads) {
+                Ok(executed) => eprintln!("Executed {} tasks", executed),
+                Err(e) => {
+                    eprintln!("error: worker failed: {}", e);
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        Ok(Command::Help) => {
+            print!("{}", cli::usage());
+            return;
+        }
+        Ok(Command::Version) => {
+            println!("taskrunner {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!("Try 'taskrunner --help' for more information.");
+            process::exit(2);
+        }
+    };
+
+    // The part of the tree that has already been completed, if any.
+    let (base, initial) = match &args.resume {
+        Some(path) => {
+            let mut checkpoint = Checkpoint::read_from(path).unwrap_or_else(|e| {
+                eprintln!("error: failed to read checkpoint {}: {}", path.display(), e);
+                process::exit(1);
+            });
+            eprintln!(
+                "Resuming from {} with {} pending tasks",
+                path.display(),
+                checkpoint.pending.len()
+            );
+            args.seed = checkpoint.seed;
+            args.starting_height = checkpoint.starting_height;
+            args.max_children = checkpoint.max_children;
+            let pending = std::mem::take(&mut checkpoint.pending);
+            (checkpoint, pending)
+        }
+        None => (
+            Checkpoint {
+                seed: args.seed,
+                starting_height: args.starting_height,
+                max_children: args.max_children,
+                ..Default::default()
+            },
+            Task::generate_initial(args.seed, args.starting_height, args.max_children),
+        ),
+    };
 
     eprintln!(
-        "Using seed {}, starting height {}, max. children {}",
-        seed, starting_height, max_children
+        "Using seed {}, starting height {}, max. children {}, threads {}, strategy {}",
+        args.seed,
+        args.starting_height,
+        args.max_children,
+        args.threads,
+        args.strategy_name()
     );
+    if let Some(policy) = args.policy {
+        eprintln!("Running ready tasks in {} order", policy);
+    }
+    if args.batch {
+        eprintln!("Dispatching tasks in batches");
+    }
 
-    let n_cpus = num_cpus::get();
-    let pool = ThreadPool::new(n_cpus);
-
-    let (send, recv) = channel();
-
-    let mut count_map = HashMap::new();
-    let mut taskq = VecDeque::from(Task::generate_initial(seed, starting_height, max_children));
-
-    let mut output: u64 = 0;
-    let mut spawned: u64 = 0;
-
-    let start = Instant::now();
-
-    while let Some(next) = taskq.pop_front() {
-        execute_task(&send, &mut count_map, &mut spawned, &pool, next);
+    if let Some(cpus) = &args.cpus {
+        if let Err(e) = affinity::pin_current_thread(cpus) {
+            eprintln!("error: failed to pin workers to cpus {:?}: {}", cpus, e);
+            process::exit(1);
+        }
+        eprintln!("Pinned workers to cpus {:?}", cpus);
     }
 
-    while spawned > 0 {
-        let new_tasks = wait_task(&recv, &mut spawned, &mut output);
-        for next in new_tasks {
-            execute_task(&send, &mut count_map, &mut spawned, &pool, next);
+    // Only the threadpool-channel schedulers can be stopped early, and only
+    // need a token when there is a deadline or a signal handler to trip it.
+    let mut cancel = None;
+    if args.listen.is_none() && args.strategy == Strategy::ThreadPoolChannel {
+        let mut token = CancelToken::new();
+        if let Some(deadline) = args.deadline {
+            token = token.with_deadline(Instant::now() + deadline);
+        }
+        match token.cancel_on_signals() {
+            Ok(()) => cancel = Some(token),
+            Err(e) => {
+                eprintln!("warning: failed to install the signal handlers: {}", e);
+                cancel = args.deadline.is_some().then_some(token);
+            }
         }
     }
 
-    let end = Instant::now();
+    let scheduler: Box<dyn Scheduler> = match (&args.listen, &args.checkpoint) {
+        (Some(addr), _) => {
+            let coordinator = Coordinator::bind(addr.as_str()).unwrap_or_else(|e| {
+                eprintln!("error: failed to listen on {}: {}", addr, e);
+                process::exit(1);
+            });
+            if let Ok(addr) = coordinator.local_addr() {
+                eprintln!("Waiting for workers on {}", addr);
+            }
+            Box::new(coordinator)
+        }
+        (None, Some(path)) => {
+            let mut scheduler = Checkpointing::new(
+                args.threads,
+                path.clone(),
+                args.checkpoint_interval,
+                base.clone(),
+            );
+            if let Some(cancel) = cancel {
+                scheduler = scheduler.with_cancel(cancel);
+            }
+            Box::new(scheduler)
+        }
+        (None, None) if args.strategy == Strategy::ThreadPoolChannel => {
+            let mut scheduler = ThreadPoolChannel::new(args.threads);
+            if let Some(cancel) = cancel {
+                scheduler = scheduler.with_cancel(cancel);
+            }
+            if let Some(max_pending) = args.max_pending {
+                scheduler = scheduler.with_max_pending(max_pending);
+            }
+            if let Some(policy) = args.policy {
+                scheduler = scheduler.with_policy(policy);
+            }
+            if let Some(interval) = args.progress {
+                scheduler = scheduler.with_progress(interval);
+            }
+            scheduler = scheduler.with_retries(args.retries);
+            if args.batch {
+                scheduler = scheduler.with_batching();
+            }
+            if let Some(path) = &args.cache {
+                let cache = OutputCache::open(path).unwrap_or_else(|e| {
+                    eprintln!("error: failed to open cache {}: {}", path.display(), e);
+                    process::exit(1);
+                });
+                eprintln!(
+                    "Using cache {} with {} outputs",
+                    path.display(),
+                    cache.len()
+                );
+                scheduler = scheduler.with_cache(Arc::new(cache));
+            }
+            Box::new(scheduler)
+        }
+        (None, None) => args.strategy.scheduler(args.threads),
+    };
 
-    eprintln!("Completed in {} s", (end - start).as_secs_f64());
+    let start = Instant::now();
+    let mut summary = scheduler.run(initial);
+    let end = Instant::now();
 
-    println!(
-        "{},{},{},{}",
-        output,
-        count_map.get(&TaskType::Hash).unwrap_or(&0),
-        count_map.get(&TaskType::Derive).unwrap_or(&0),
-        count_map.get(&TaskType::Random).unwrap_or(&0)
-    );
-}
+    summary.merge(&base.summary());
 
-// There should be no need to modify anything below
+    if let Some(failure) = &summary.failure {
+        eprintln!("error: {}", failure);
+    }
+    match summary.unfinished {
+        Some(unfinished) => {
+            eprintln!(
+                "Stopped after {} s with {} tasks left, the result is incomplete",
+                (end - start).as_secs_f64(),
+                unfinished
+            );
+            if let Some(path) = &args.checkpoint {
+                eprintln!("Continue with --resume {}", path.display());
+            }
+        }
+        None => eprintln!("Completed in {} s", (end - start).as_secs_f64()),
+    }
+    if let Some(peak_pending) = summary.peak_pending {
+        eprintln!("Peak pending tasks {}", peak_pending);
+    }
+    if let Some(cache) = summary.cache {
+        eprintln!("Cache hits {}, misses {}", cache.hits, cache.misses);
+    }
+    let peak_rss = stats::peak_rss_bytes();
+    if let Some(peak_rss) = peak_rss {
+        eprintln!("Peak RSS {:.1} MB", peak_rss as f64 / (1024.0 * 1024.0));
+    }
 
-fn get_args() -> (u64, usize, usize) {
-    let mut args = std::env::args().skip(1);
-    (
-        args.next()
-            .map(|a| a.parse().expect("invalid u64 for seed"))
-            .unwrap_or_else(|| rand::Rng::gen(&mut rand::thread_rng())),
-        args.next()
-            .map(|a| a.parse().expect("invalid usize for starting_height"))
-            .unwrap_or(5),
-        args.next()
-            .map(|a| a.parse().expect("invalid u64 for seed"))
-            .unwrap_or(5),
-    )
+    let report = Report {
+        args: &args,
+        summary: &summary,
+        wall_time: end - start,
+        peak_rss,
+    };
+    if args.timings {
+        eprint!("{}", report.timing_table());
+    }
+    println!("{}", report.render(args.output_format));
+    if summary.unfinished.is_some() {
+        process::exit(1);
+    }
 }
-
-mod task;
@@ -0,0 +1,110 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    sync::mpsc::{channel, RecvTimeoutError, Sender},
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::task::{TaskType, MAX_KINDS};
+
+/// Counters a scheduler keeps up to date while it runs, for `ProgressReporter`.
+/// Workers only bump an atomic per completed task, the rest is set by the
+/// dispatching thread.
+#[derive(Debug, Default)]
+pub struct Progress {
+    completed: [AtomicUsize; MAX_KINDS],
+    queued: AtomicUsize,
+    in_flight: AtomicUsize,
+    depth: AtomicUsize,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Progress::default()
+    }
+
+    pub fn complete(&self, typ: TaskType) {
+        self.completed[typ.index()].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Tasks waiting to be dispatched and tasks handed to the workers.
+    pub fn set_pending(&self, queued: usize, in_flight: usize) {
+        self.queued.store(queued, Ordering::Relaxed);
+        self.in_flight.store(in_flight, Ordering::Relaxed);
+    }
+
+    /// Depth below the initial set of the task dispatched last.
+    pub fn set_depth(&self, depth: usize) {
+        self.depth.store(depth, Ordering::Relaxed);
+    }
+
+    pub fn completed(&self) -> usize {
+        self.completed
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    fn line(&self, elapsed: Duration, rate: f64) -> String {
+        let completed: Vec<_> = TaskType::all()
+            .into_iter()
+            .map(|typ| {
+                format!(
+                    "{} {}",
+                    self.completed[typ.index()].load(Ordering::Relaxed),
+                    typ.name()
+                )
+            })
+            .collect();
+        format!(
+            "[{:>7.1} s] done {} | queued {}, in flight {} | depth {} | {:.1} tasks/s",
+            elapsed.as_secs_f64(),
+            completed.join(", "),
+            self.queued.load(Ordering::Relaxed),
+            self.in_flight.load(Ordering::Relaxed),
+            self.depth.load(Ordering::Relaxed),
+            rate
+        )
+    }
+}
+
+/// Prints a line with the state of a `Progress` on stderr every `interval`,
+/// from its own thread so it keeps going when no task completes, until it is
+/// dropped.
+pub struct ProgressReporter {
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ProgressReporter {
+    pub fn start(progress: Arc<Progress>, interval: Duration) -> Self {
+        let (stop, stopped) = channel::<()>();
+        let handle = thread::spawn(move || {
+            let start = Instant::now();
+            let mut last = (start, progress.completed());
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let now = Instant::now();
+                let completed = progress.completed();
+                // throughput over the last interval only, so stalls show up
+                let rate = (completed - last.1) as f64 / (now - last.0).as_secs_f64();
+                eprintln!("{}", progress.line(now - start, rate));
+                last = (now, completed);
+            }
+        });
+        ProgressReporter {
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for ProgressReporter {
+    fn drop(&mut self) {
+        // Disconnecting the channel wakes the thread up.
+        self.stop.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
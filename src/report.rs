@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+use taskrunner::{task::TaskType, tree::json_str, RunSummary};
+
+use crate::cli::{Args, OutputFormat};
+
+fn json_opt<T: ToString>(value: Option<T>) -> String {
+    value.map_or_else(|| "null".to_string(), |v| v.to_string())
+}
+
+/// The members of a JSON object with one entry per task type, keyed by name.
+fn per_type(value: impl Fn(TaskType) -> String) -> String {
+    let members: Vec<_> = TaskType::all()
+        .into_iter()
+        .map(|typ| format!("{}:{}", json_str(typ.name()), value(typ)))
+        .collect();
+    members.join(",")
+}
+
+/// Everything that is printed on stdout once a run has finished.
+pub struct Report<'a> {
+    pub args: &'a Args,
+    pub summary: &'a RunSummary,
+    pub wall_time: Duration,
+    pub peak_rss: Option<u64>,
+}
+
+impl Report<'_> {
+    pub fn render(&self, format: OutputFormat) -> String {
+        match format {
+            OutputFormat::Csv if self.summary.unfinished.is_some() => {
+                format!("{},incomplete", self.summary)
+            }
+            OutputFormat::Csv => self.summary.to_string(),
+            OutputFormat::Json => self.json(),
+        }
+    }
+
+    fn json(&self) -> String {
+        let args = self.args;
+        let summary = self.summary;
+        format!(
+            concat!(
+                "{{\"seed\":{},\"starting_height\":{},\"max_children\":{},\"threads\":{},\"cpus\":{},",
+                "\"strategy\":\"{}\",\"policy\":{},\"complete\":{},\"unfinished_tasks\":{},\"error\":{},\"output\":{},",
+                "\"counts\":{{{}}},",
+                "\"total_tasks\":{},\"wall_time_s\":{},\"peak_pending\":{},\"peak_rss_bytes\":{},\"cache\":{},",
+                "\"timings\":{{{}}}}}"
+            ),
+            args.seed,
+            args.starting_height,
+            args.max_children,
+            args.threads,
+            match &args.cpus {
+                Some(cpus) => format!("{:?}", cpus),
+                None => "null".to_string(),
+            },
+            args.strategy_name(),
+            json_opt(args.policy.map(|policy| format!("\"{}\"", policy))),
+            summary.unfinished.is_none(),
+            json_opt(summary.unfinished),
+            json_opt(summary.failure.as_ref().map(|failure| json_str(&failure.to_string()))),
+            summary.output,
+            per_type(|typ| summary.count(typ).to_string()),
+            summary.total(),
+            self.wall_time.as_secs_f64(),
+            json_opt(summary.peak_pending),
+            json_opt(self.peak_rss),
+            match summary.cache {
+                Some(cache) => format!("{{\"hits\":{},\"misses\":{}}}", cache.hits, cache.misses),
+                None => "null".to_string(),
+            },
+            per_type(|typ| self.timing_json(typ))
+        )
+    }
+
+    fn timing_json(&self, typ: TaskType) -> String {
+        match self.summary.timings.get(&typ) {
+            Some(stats) => format!(
+                "{{\"count\":{},\"min_s\":{},\"mean_s\":{},\"p50_s\":{},\"p99_s\":{},\"max_s\":{},\"total_s\":{}}}",
+                stats.count,
+                stats.min.as_secs_f64(),
+                stats.mean.as_secs_f64(),
+                stats.p50.as_secs_f64(),
+                stats.p99.as_secs_f64(),
+                stats.max.as_secs_f64(),
+                stats.total.as_secs_f64()
+            ),
+            None => "null".to_string(),
+        }
+    }
+
+    /// A human readable table of the per-type latencies, in milliseconds.
+    pub fn timing_table(&self) -> String {
+        let mut table = format!(
+            "{:<8}{:>8}{:>10}{:>10}{:>10}{:>10}{:>10}{:>12}\n",
+            "type", "count", "min ms", "mean ms", "p50 ms", "p99 ms", "max ms", "cpu time s"
+        );
+        for typ in TaskType::all() {
+            let stats = self.summary.timings.get(&typ).copied().unwrap_or_default();
+            let ms = |d: std::time::Duration| format!("{:.3}", d.as_secs_f64() * 1e3);
+            table += &format!(
+                "{:<8}{:>8}{:>10}{:>10}{:>10}{:>10}{:>10}{:>12.3}\n",
+                typ.name(),
+                stats.count,
+                ms(stats.min),
+                ms(stats.mean),
+                ms(stats.p50),
+                ms(stats.p99),
+                ms(stats.max),
+                stats.total.as_secs_f64()
+            );
+        }
+        table
+    }
+}
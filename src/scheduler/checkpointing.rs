@@ -0,0 +1,178 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    sync::mpsc::channel,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use threadpool::ThreadPool;
+
+use super::{RunSummary, Scheduler, TaskFailure};
+use crate::{
+    cancel::CancelToken,
+    checkpoint::Checkpoint,
+    stats::TimingRecorder,
+    task::{Task, TaskType},
+};
+
+/// `ThreadPoolChannel` which also remembers the tasks it has in flight, so it
+/// can periodically write a `Checkpoint` of the whole frontier to `path`.
+///
+/// `base` holds the parameters of the run and the part of the tree that was
+/// completed before this scheduler started (e.g. when resuming), so the
+/// checkpoints always describe the whole tree. A final checkpoint without any
+/// pending tasks is written once the run completes.
+///
+/// With `with_cancel` the run stops as soon as the token is cancelled, the
+/// tasks in flight are abandoned and left pending in the final checkpoint.
+/// Abandoned tasks which already started keep running on the pool until they
+/// finish, their results are dropped; the others are skipped.
+///
+/// A task which panics stops the run the same way. It is left pending in the
+/// final checkpoint and the summary holds the `TaskFailure`.
+pub struct Checkpointing {
+    n_threads: usize,
+    path: PathBuf,
+    interval: Duration,
+    base: Checkpoint,
+    cancel: Option<CancelToken>,
+}
+
+impl Checkpointing {
+    pub fn new(n_threads: usize, path: PathBuf, interval: Duration, base: Checkpoint) -> Self {
+        Checkpointing {
+            n_threads,
+            path,
+            interval,
+            base,
+            cancel: None,
+        }
+    }
+
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    fn checkpoint(
+        &self,
+        output: u64,
+        count_map: &HashMap<TaskType, usize>,
+        in_flight: &BTreeMap<u64, Task>,
+        taskq: &VecDeque<Task>,
+    ) {
+        let mut checkpoint = Checkpoint {
+            pending: in_flight.values().chain(taskq).cloned().collect(),
+            ..self.base.clone()
+        };
+        checkpoint.output ^= output;
+        for (&typ, &count) in count_map {
+            *checkpoint.count_map.entry(typ).or_insert(0usize) += count;
+        }
+
+        // A failed checkpoint should not take the run down with it.
+        if let Err(e) = checkpoint.write_to(&self.path) {
+            eprintln!(
+                "warning: failed to write checkpoint to {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+impl Scheduler for Checkpointing {
+    fn run(&self, initial: Vec<Task>) -> RunSummary {
+        let pool = ThreadPool::new(self.n_threads);
+
+        let (send, recv) = channel();
+        let recorder = Arc::new(TimingRecorder::new());
+
+        // Only completed tasks are counted, in-flight ones are part of the
+        // frontier and will be counted again when resuming.
+        let mut count_map = HashMap::new();
+        let mut taskq = VecDeque::from(initial);
+        let mut in_flight = BTreeMap::new();
+        let mut next_id: u64 = 0;
+
+        let mut output: u64 = 0;
+        let mut last_checkpoint = Instant::now();
+        let mut cancelled = false;
+        let mut failure = None;
+        // set once a task failed, so the queued jobs are skipped as well
+        let failed = Arc::new(AtomicBool::new(false));
+
+        loop {
+            if failure.is_some() || self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+                cancelled = true;
+                break;
+            }
+
+            while let Some(next) = taskq.pop_front() {
+                let send = send.clone();
+                let recorder = recorder.clone();
+                let cancel = self.cancel.clone();
+                let failed = failed.clone();
+                let id = next_id;
+                next_id += 1;
+                in_flight.insert(id, next.clone());
+                pool.execute(move || {
+                    // A skipped task still reports back, in case the run has
+                    // not noticed the cancellation yet and waits for it.
+                    let stopped = failed.load(Ordering::SeqCst)
+                        || cancel.as_ref().is_some_and(CancelToken::is_cancelled);
+                    let result = (!stopped).then(|| {
+                        panic::catch_unwind(AssertUnwindSafe(|| {
+                            recorder.time(next.typ, || next.execute())
+                        }))
+                        .map_err(|payload| {
+                            failed.store(true, Ordering::SeqCst);
+                            TaskFailure::from_panic(next, 1, &*payload)
+                        })
+                    });
+                    // Nobody listens any more once the run stopped.
+                    let _ = send.send((id, result));
+                });
+            }
+
+            if in_flight.is_empty() {
+                break;
+            }
+
+            let (id, result) = recv.recv().unwrap();
+            let (result, children) = match result {
+                Some(Ok(result)) => result,
+                // stays pending, and stops the run
+                Some(Err(e)) => {
+                    failure.get_or_insert(e);
+                    continue;
+                }
+                // skipped, so it stays pending
+                None => continue,
+            };
+            let task = in_flight.remove(&id).unwrap();
+            *count_map.entry(task.typ).or_insert(0usize) += 1;
+            output ^= result;
+            taskq.extend(children);
+
+            if last_checkpoint.elapsed() >= self.interval {
+                self.checkpoint(output, &count_map, &in_flight, &taskq);
+                last_checkpoint = Instant::now();
+            }
+        }
+
+        self.checkpoint(output, &count_map, &in_flight, &taskq);
+
+        RunSummary {
+            output,
+            count_map,
+            timings: recorder.summarize(),
+            unfinished: cancelled.then_some(in_flight.len() + taskq.len()),
+            failure,
+            ..Default::default()
+        }
+    }
+}
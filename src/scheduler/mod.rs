@@ -0,0 +1,224 @@
+use std::{any::Any, collections::HashMap, error::Error, fmt, str::FromStr};
+
+use crate::{
+    cache::CacheStats,
+    stats::LatencyStats,
+    task::{Task, TaskType},
+};
+
+mod checkpointing;
+mod policy;
+#[cfg(feature = "rayon")]
+mod rayon_recursive;
+mod serial_dfs;
+mod threadpool_channel;
+mod threadpool_dfs;
+mod threadpool_mutex;
+mod threadpool_recv;
+mod threadpool_try_channel;
+#[cfg(feature = "tokio")]
+mod tokio_async;
+mod work_stealing;
+
+pub use checkpointing::Checkpointing;
+pub use policy::{Policy, POLICIES};
+#[cfg(feature = "rayon")]
+pub use rayon_recursive::RayonRecursive;
+pub use serial_dfs::SerialDfs;
+pub use threadpool_channel::ThreadPoolChannel;
+pub use threadpool_dfs::ThreadPoolDfs;
+pub use threadpool_mutex::ThreadPoolMutex;
+pub use threadpool_recv::ThreadPoolRecv;
+pub use threadpool_try_channel::ThreadPoolTryChannel;
+#[cfg(feature = "tokio")]
+pub use tokio_async::TokioAsync;
+pub use work_stealing::WorkStealing;
+
+/// Something that can drive a task tree to completion, starting from the
+/// tasks produced by `Task::generate_initial`.
+pub trait Scheduler {
+    fn run(&self, initial: Vec<Task>) -> RunSummary;
+}
+
+/// The result of running a whole task tree: the XOR of every task output,
+/// how many tasks of each type were executed and how long they took.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RunSummary {
+    pub output: u64,
+    pub count_map: HashMap<TaskType, usize>,
+    pub timings: HashMap<TaskType, LatencyStats>,
+    /// The largest number of tasks that were queued or in flight at once, for
+    /// schedulers that keep track of it.
+    pub peak_pending: Option<usize>,
+    /// Lookups in the output cache, for schedulers that were given one.
+    pub cache: Option<CacheStats>,
+    /// How many tasks were left in the frontier when the run was cancelled or
+    /// failed, `None` if it ran to completion.
+    pub unfinished: Option<usize>,
+    /// The task which stopped the run, for schedulers that catch panics.
+    pub failure: Option<TaskFailure>,
+}
+
+/// A task which panicked on every attempt to run it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TaskFailure {
+    pub task: Task,
+    pub attempts: usize,
+    /// The message the task panicked with the last time.
+    pub message: String,
+}
+
+impl TaskFailure {
+    pub fn from_panic(task: Task, attempts: usize, payload: &(dyn Any + Send)) -> Self {
+        let message = match payload.downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => payload
+                .downcast_ref::<String>()
+                .cloned()
+                .unwrap_or_else(|| "unknown panic".to_string()),
+        };
+        TaskFailure {
+            task,
+            attempts,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for TaskFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} task with seed {} at height {} panicked {} time(s): {}",
+            self.task.typ.name(),
+            self.task.seed,
+            self.task.height,
+            self.attempts,
+            self.message
+        )
+    }
+}
+
+impl Error for TaskFailure {}
+
+impl RunSummary {
+    pub fn count(&self, typ: TaskType) -> usize {
+        *self.count_map.get(&typ).unwrap_or(&0)
+    }
+
+    pub fn total(&self) -> usize {
+        self.count_map.values().sum()
+    }
+
+    /// Folds the output and counts of a disjoint part of the same tree into
+    /// this summary. Timings are kept as they are, since they cannot be merged
+    /// without the individual samples.
+    pub fn merge(&mut self, other: &RunSummary) {
+        self.output ^= other.output;
+        for (&typ, &count) in &other.count_map {
+            *self.count_map.entry(typ).or_insert(0usize) += count;
+        }
+    }
+}
+
+// Same format as the original `output,hash,derive,random` line, followed by
+// the counts of any other registered kinds.
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.output)?;
+        for typ in TaskType::all() {
+            write!(f, ",{}", self.count(typ))?;
+        }
+        Ok(())
+    }
+}
+
+/// Every scheduler that can be picked by name at runtime.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Strategy {
+    #[default]
+    ThreadPoolChannel,
+    ThreadPoolMutex,
+    ThreadPoolTryChannel,
+    #[cfg(feature = "tokio")]
+    TokioAsync,
+    SerialDfs,
+    ThreadPoolDfs,
+    ThreadPoolRecv,
+    WorkStealing,
+    #[cfg(feature = "rayon")]
+    RayonRecursive,
+}
+
+pub static STRATEGIES: &[Strategy] = &[
+    Strategy::ThreadPoolChannel,
+    Strategy::ThreadPoolMutex,
+    Strategy::ThreadPoolTryChannel,
+    #[cfg(feature = "tokio")]
+    Strategy::TokioAsync,
+    Strategy::SerialDfs,
+    Strategy::ThreadPoolDfs,
+    Strategy::ThreadPoolRecv,
+    Strategy::WorkStealing,
+    #[cfg(feature = "rayon")]
+    Strategy::RayonRecursive,
+];
+
+impl Strategy {
+    pub fn name(self) -> &'static str {
+        match self {
+            Strategy::ThreadPoolChannel => "threadpool-channel",
+            Strategy::ThreadPoolMutex => "threadpool-mutex",
+            Strategy::ThreadPoolTryChannel => "threadpool-try_channel",
+            #[cfg(feature = "tokio")]
+            Strategy::TokioAsync => "tokio-async",
+            Strategy::SerialDfs => "serial-dfs",
+            Strategy::ThreadPoolDfs => "threadpool-dfs",
+            Strategy::ThreadPoolRecv => "threadpool-recv",
+            Strategy::WorkStealing => "work-stealing",
+            #[cfg(feature = "rayon")]
+            Strategy::RayonRecursive => "rayon-recursive",
+        }
+    }
+
+    pub fn scheduler(self, n_threads: usize) -> Box<dyn Scheduler> {
+        match self {
+            Strategy::ThreadPoolChannel => Box::new(ThreadPoolChannel::new(n_threads)),
+            Strategy::ThreadPoolMutex => Box::new(ThreadPoolMutex::new(n_threads)),
+            Strategy::ThreadPoolTryChannel => Box::new(ThreadPoolTryChannel::new(n_threads)),
+            #[cfg(feature = "tokio")]
+            Strategy::TokioAsync => Box::new(TokioAsync::new(n_threads)),
+            Strategy::SerialDfs => Box::new(SerialDfs),
+            Strategy::ThreadPoolDfs => Box::new(ThreadPoolDfs::new(n_threads)),
+            Strategy::ThreadPoolRecv => Box::new(ThreadPoolRecv::new(n_threads)),
+            Strategy::WorkStealing => Box::new(WorkStealing::new(n_threads)),
+            #[cfg(feature = "rayon")]
+            Strategy::RayonRecursive => Box::new(RayonRecursive::new(n_threads)),
+        }
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        STRATEGIES
+            .iter()
+            .copied()
+            .find(|strategy| strategy.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = STRATEGIES.iter().map(|strategy| strategy.name()).collect();
+                format!(
+                    "unknown strategy '{}', expected one of: {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
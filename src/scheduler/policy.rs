@@ -0,0 +1,172 @@
+use std::{cmp::Ordering, collections::BinaryHeap, fmt, str::FromStr};
+
+use crate::task::{Task, TaskType};
+
+/// The order in which ready tasks are handed to the workers.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Policy {
+    /// Oldest task first, i.e. breadth first.
+    #[default]
+    Fifo,
+    /// Newest task first, i.e. depth first.
+    Lifo,
+    /// Tasks closest to the root first.
+    HighestHeight,
+    /// Random before Hash before Derive and any other kinds.
+    CheapestType,
+    /// Tasks with the most expected descendants first, the more rounds the
+    /// earlier among those of the same height.
+    LargestSubtree,
+}
+
+pub static POLICIES: &[Policy] = &[
+    Policy::Fifo,
+    Policy::Lifo,
+    Policy::HighestHeight,
+    Policy::CheapestType,
+    Policy::LargestSubtree,
+];
+
+impl Policy {
+    pub fn name(self) -> &'static str {
+        match self {
+            Policy::Fifo => "fifo",
+            Policy::Lifo => "lifo",
+            Policy::HighestHeight => "highest-height",
+            Policy::CheapestType => "cheapest-type",
+            Policy::LargestSubtree => "largest-subtree",
+        }
+    }
+
+    /// Tasks with a larger key run first, ties are broken by arrival order.
+    fn key(self, task: &Task) -> (u64, u64) {
+        match self {
+            Policy::Fifo | Policy::Lifo => (0, 0),
+            Policy::HighestHeight => (task.height as u64, 0),
+            Policy::CheapestType => (
+                match task.typ {
+                    TaskType::Random => 2,
+                    TaskType::Hash => 1,
+                    TaskType::Derive => 0,
+                    // nothing is known about the cost of other kinds
+                    _ => 0,
+                },
+                0,
+            ),
+            Policy::LargestSubtree => (expected_subtree(task), task.rounds() as u64),
+        }
+    }
+}
+
+/// The expected number of tasks in the subtree of `task` in 1/1024ths: every
+/// task has between 0 and `max_children` children, so `max_children / 2` on
+/// average at every level below it.
+fn expected_subtree(task: &Task) -> u64 {
+    let branching = task.max_children as f64 / 2.0;
+    let mut level = 1.0;
+    let mut total = 1.0;
+    for _ in 0..task.height {
+        level *= branching;
+        total += level;
+    }
+    // saturates instead of overflowing for huge trees
+    (total * 1024.0) as u64
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Policy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        POLICIES
+            .iter()
+            .copied()
+            .find(|policy| policy.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = POLICIES.iter().map(|policy| policy.name()).collect();
+                format!(
+                    "unknown policy '{}', expected one of: {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+struct Entry {
+    key: (u64, u64),
+    // arrival order, negated for every policy except `Lifo`
+    order: u64,
+    task: Task,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.key, self.order).cmp(&(other.key, other.order))
+    }
+}
+
+/// The tasks waiting for a worker, ordered by a `Policy`.
+pub(crate) struct ReadyQueue {
+    policy: Policy,
+    heap: BinaryHeap<Entry>,
+    arrivals: u64,
+}
+
+impl ReadyQueue {
+    pub fn new(policy: Policy) -> Self {
+        ReadyQueue {
+            policy,
+            heap: BinaryHeap::new(),
+            arrivals: 0,
+        }
+    }
+
+    pub fn push(&mut self, task: Task) {
+        self.arrivals += 1;
+        let order = match self.policy {
+            Policy::Lifo => self.arrivals,
+            _ => u64::MAX - self.arrivals,
+        };
+        self.heap.push(Entry {
+            key: self.policy.key(&task),
+            order,
+            task,
+        });
+    }
+
+    pub fn pop(&mut self) -> Option<Task> {
+        self.heap.pop().map(|entry| entry.task)
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+}
+
+impl Extend<Task> for ReadyQueue {
+    fn extend<I: IntoIterator<Item = Task>>(&mut self, tasks: I) {
+        for task in tasks {
+            self.push(task);
+        }
+    }
+}
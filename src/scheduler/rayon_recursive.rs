@@ -0,0 +1,54 @@
+use super::{RunSummary, Scheduler};
+use crate::{aggregate::Tally, stats::TimingRecorder, task::Task};
+
+/// Runs every subtree as a rayon job: a set of siblings is split in halves
+/// with `rayon::join` until single tasks remain, and each task recurses into
+/// its own children once it has run. The `Tally` of every subtree is combined
+/// on the way back up, so there is no shared state besides the timings.
+pub struct RayonRecursive {
+    n_threads: usize,
+}
+
+impl RayonRecursive {
+    pub fn new(n_threads: usize) -> Self {
+        RayonRecursive { n_threads }
+    }
+}
+
+fn run_task(task: Task, recorder: &TimingRecorder) -> Tally {
+    let (output, children) = recorder.time(task.typ, || task.execute());
+    let mut tally = run_set(children, recorder);
+    tally.record(task.typ, output);
+    tally
+}
+
+fn run_set(mut tasks: Vec<Task>, recorder: &TimingRecorder) -> Tally {
+    match tasks.len() {
+        0 => Tally::default(),
+        1 => run_task(tasks.pop().unwrap(), recorder),
+        n => {
+            let right = tasks.split_off(n / 2);
+            let (mut left, right) =
+                rayon::join(|| run_set(tasks, recorder), || run_set(right, recorder));
+            left.merge(&right);
+            left
+        }
+    }
+}
+
+impl Scheduler for RayonRecursive {
+    fn run(&self, initial: Vec<Task>) -> RunSummary {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.n_threads)
+            .build()
+            .expect("failed to build rayon thread pool");
+        let recorder = TimingRecorder::new();
+
+        let tally = pool.install(|| run_set(initial, &recorder));
+
+        RunSummary {
+            timings: recorder.summarize(),
+            ..tally.summary()
+        }
+    }
+}
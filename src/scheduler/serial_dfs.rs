@@ -0,0 +1,39 @@
+use std::collections::{HashMap, VecDeque};
+
+use super::{RunSummary, Scheduler};
+use crate::{lazy::LazyTask, stats::TimingRecorder, task::Task};
+
+/// Runs the whole tree on the calling thread, depth first, generating
+/// siblings lazily so the queue never holds more than two tasks per level.
+pub struct SerialDfs;
+
+impl Scheduler for SerialDfs {
+    fn run(&self, initial: Vec<Task>) -> RunSummary {
+        let mut count_map = HashMap::new();
+        let mut taskq: VecDeque<LazyTask> = initial.into_iter().map(LazyTask::from).collect();
+
+        let mut output: u64 = 0;
+        let recorder = TimingRecorder::new();
+
+        while let Some(next) = taskq.pop_front() {
+            *count_map.entry(next.task.typ).or_insert(0usize) += 1;
+            let (result, child_task, sibling_task) =
+                recorder.time(next.task.typ, || next.execute());
+            if let Some(sibling_task) = sibling_task {
+                taskq.push_front(sibling_task);
+            }
+
+            if let Some(child_task) = child_task {
+                taskq.push_front(child_task);
+            }
+            output ^= result;
+        }
+
+        RunSummary {
+            output,
+            count_map,
+            timings: recorder.summarize(),
+            ..Default::default()
+        }
+    }
+}
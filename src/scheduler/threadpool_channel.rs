@@ -0,0 +1,643 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    panic::{self, AssertUnwindSafe},
+    sync::mpsc::channel,
+    sync::mpsc::Receiver,
+    sync::mpsc::Sender,
+    sync::Arc,
+    time::Duration,
+};
+
+use threadpool::ThreadPool;
+
+use super::{
+    policy::{Policy, ReadyQueue},
+    RunSummary, Scheduler, TaskFailure,
+};
+use crate::{
+    cache::OutputCache,
+    cancel::CancelToken,
+    lazy::LazyTask,
+    progress::{Progress, ProgressReporter},
+    stats::TimingRecorder,
+    task::{Task, TaskResult, TaskType},
+};
+
+/// Every task runs on a `ThreadPool`, results come back to the calling thread
+/// over a single channel and the children are dispatched from there.
+///
+/// With `with_max_pending` the number of queued and in-flight tasks is kept
+/// below the given limit, counting a set of siblings which is still drawn one
+/// at a time (the initial set included) as a single task. Tasks are expanded
+/// eagerly while all their children fit, otherwise their children are
+/// generated lazily one sibling at a time and the tree is expanded depth
+/// first, which only needs one task per level. So any limit of at least the
+/// height of the tree plus one is kept, a lower one is exceeded as little as
+/// possible.
+///
+/// With `with_policy` only as many tasks as there are threads are handed to
+/// the pool at once, the others wait in a queue ordered by the `Policy`.
+/// `with_max_pending` takes precedence over it.
+///
+/// With `with_cache` the output of every task is looked up in an
+/// `OutputCache` first and only computed when it is missing.
+///
+/// With `with_cancel` no more tasks are dispatched once the token is
+/// cancelled, the ones already running are waited for, the ones still waiting
+/// in the pool are skipped and the summary only covers the tasks that did run.
+///
+/// With `with_progress` a `ProgressReporter` prints the state of the run at
+/// the given interval.
+///
+/// With `with_batching` tasks are handed to the pool in batches, each one a
+/// single job whose results come back as one message, which saves a closure
+/// and a channel round trip per task when the tasks are cheap. The batch size
+/// follows the depth of the queue, so that a shallow frontier is still spread
+/// over all threads. `with_max_pending` and `with_policy` take precedence
+/// over it.
+///
+/// A task which panics is run again up to `with_retries` times. If it keeps
+/// panicking the run stops as if it had been cancelled, and the summary holds
+/// the `TaskFailure`.
+pub struct ThreadPoolChannel {
+    n_threads: usize,
+    max_pending: Option<usize>,
+    policy: Option<Policy>,
+    cache: Option<Arc<OutputCache>>,
+    cancel: Option<CancelToken>,
+    progress: Option<Duration>,
+    retries: usize,
+    batching: bool,
+}
+
+/// The most tasks that go into a single batch.
+const MAX_BATCH: usize = 64;
+
+impl ThreadPoolChannel {
+    pub fn new(n_threads: usize) -> Self {
+        ThreadPoolChannel {
+            n_threads,
+            max_pending: None,
+            policy: None,
+            cache: None,
+            cancel: None,
+            progress: None,
+            retries: 0,
+            batching: false,
+        }
+    }
+
+    pub fn with_max_pending(mut self, max_pending: usize) -> Self {
+        self.max_pending = Some(max_pending);
+        self
+    }
+
+    pub fn with_policy(mut self, policy: Policy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    pub fn with_cache(mut self, cache: Arc<OutputCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    pub fn with_progress(mut self, interval: Duration) -> Self {
+        self.progress = Some(interval);
+        self
+    }
+
+    pub fn with_retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn with_batching(mut self) -> Self {
+        self.batching = true;
+        self
+    }
+}
+
+/// Everything the jobs of one run share besides the channel.
+struct Shared {
+    recorder: TimingRecorder,
+    cache: Option<Arc<OutputCache>>,
+    progress: Option<Arc<Progress>>,
+    // height of the initial set, to turn heights into depths
+    top_height: usize,
+    retries: usize,
+    cancel: Option<CancelToken>,
+}
+
+/// What a job sends back for one task.
+enum Outcome {
+    Done(TaskResult),
+    Failed(TaskFailure),
+    /// The run was cancelled before the job got to the task.
+    Skipped(Task),
+}
+
+impl Shared {
+    /// Runs `job` for `task`, again if it panics, until it has failed
+    /// `retries` more times than once. The task counts as completed once,
+    /// whether it succeeded or finally failed.
+    fn attempt<T>(&self, task: &Task, job: impl Fn() -> T) -> Result<T, TaskFailure> {
+        let mut attempts = 0;
+        let result = loop {
+            attempts += 1;
+            match panic::catch_unwind(AssertUnwindSafe(&job)) {
+                Ok(result) => break Ok(result),
+                Err(payload) if attempts > self.retries => {
+                    break Err(TaskFailure::from_panic(task.clone(), attempts, &*payload));
+                }
+                Err(_) => {}
+            }
+        };
+        if let Some(progress) = &self.progress {
+            progress.complete(task.typ);
+        }
+        result
+    }
+
+    /// Runs the body of `task`, unless its output is already in the cache.
+    /// Only bodies which actually run are timed.
+    fn compute(&self, task: &Task) -> u64 {
+        let compute = || self.recorder.time(task.typ, || task.compute());
+        match &self.cache {
+            Some(cache) => cache.get_or_compute(task, compute),
+            None => compute(),
+        }
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(CancelToken::is_cancelled)
+    }
+
+    /// Runs `task` and its retries on a worker, unless the run has been
+    /// cancelled in the meantime.
+    fn run(&self, task: Task) -> Outcome {
+        if self.cancelled() {
+            return Outcome::Skipped(task);
+        }
+        let result = self.attempt(&task, || {
+            let output = self.compute(&task);
+            (output, task.children(output).collect())
+        });
+        match result {
+            Ok(result) => Outcome::Done(result),
+            Err(e) => Outcome::Failed(e),
+        }
+    }
+
+    fn dispatched(&self, task: &Task) {
+        if let Some(progress) = &self.progress {
+            progress.set_depth(self.top_height.saturating_sub(task.height));
+        }
+    }
+
+    fn pending(&self, queued: usize, in_flight: usize) {
+        if let Some(progress) = &self.progress {
+            progress.set_pending(queued, in_flight);
+        }
+    }
+}
+
+fn execute_task(
+    send: &Sender<Outcome>,
+    count_map: &mut HashMap<TaskType, usize>,
+    spawned: &mut u64,
+    pool: &ThreadPool,
+    shared: &Arc<Shared>,
+    next: Task,
+) {
+    let send = send.clone();
+    let shared_job = shared.clone();
+    *count_map.entry(next.typ).or_insert(0usize) += 1;
+    *spawned += 1;
+    shared.dispatched(&next);
+    pool.execute(move || send.send(shared_job.run(next)).unwrap());
+}
+
+fn wait_task(recv: &Receiver<Outcome>, spawned: &mut u64, output: &mut u64) -> Outcome {
+    let outcome = recv.recv().unwrap();
+    *spawned -= 1;
+    if let Outcome::Done((result, _)) = &outcome {
+        *output ^= result;
+    }
+    outcome
+}
+
+/// How many of `queued` tasks go into the next batch: enough to give every
+/// thread two batches, but never more than `MAX_BATCH`.
+fn batch_size(queued: usize, n_threads: usize) -> usize {
+    (queued / (2 * n_threads.max(1))).clamp(1, MAX_BATCH)
+}
+
+/// Takes a failed task out of the counts again and keeps the first failure.
+fn failed(
+    count_map: &mut HashMap<TaskType, usize>,
+    first: &mut Option<TaskFailure>,
+    failure: TaskFailure,
+) {
+    skipped(count_map, &failure.task);
+    first.get_or_insert(failure);
+}
+
+/// Takes a task which did not run out of the counts again.
+fn skipped(count_map: &mut HashMap<TaskType, usize>, task: &Task) {
+    *count_map.get_mut(&task.typ).unwrap() -= 1;
+}
+
+/// Executes `next` and returns the tasks that have to run after it. When
+/// `eager`, all children are materialised at once like `Task::execute` does,
+/// otherwise only the first child and the next sibling.
+fn expand(next: &LazyTask, eager: bool, shared: &Shared) -> (u64, Vec<LazyTask>) {
+    let output = shared.compute(&next.task);
+    let mut new_tasks: Vec<LazyTask> = next.sibling().into_iter().collect();
+    if eager {
+        new_tasks.extend(next.task.children(output).map(LazyTask::from));
+    } else {
+        // pushed last, so it is the first to be popped
+        new_tasks.extend(LazyTask::first_of(next.task.children(output)));
+    }
+    (output, new_tasks)
+}
+
+/// The most tasks `expand` can return for `next`.
+fn returned(next: &LazyTask, eager: bool) -> usize {
+    let children = match (next.task.height, eager) {
+        (0, _) => 0,
+        (_, true) => next.task.max_children,
+        (_, false) => 1,
+    };
+    children + usize::from(next.siblings.len() > 0)
+}
+
+impl ThreadPoolChannel {
+    fn run_unbounded(&self, initial: Vec<Task>, shared: &Arc<Shared>) -> RunSummary {
+        let pool = ThreadPool::new(self.n_threads);
+
+        let (send, recv) = channel();
+
+        let mut count_map = HashMap::new();
+        let mut taskq = VecDeque::from(initial);
+
+        let mut output: u64 = 0;
+        let mut spawned: u64 = 0;
+        let mut cancelled = false;
+        let mut failure = None;
+
+        while let Some(next) = taskq.pop_front() {
+            execute_task(&send, &mut count_map, &mut spawned, &pool, shared, next);
+        }
+        let mut peak_pending = spawned;
+
+        while spawned > 0 {
+            cancelled = cancelled || failure.is_some() || shared.cancelled();
+            // After a failure or a cancellation the tasks already in the pool
+            // still run, but their children are left in the queue.
+            match wait_task(&recv, &mut spawned, &mut output) {
+                Outcome::Done((_, new_tasks)) if !cancelled => {
+                    for next in new_tasks {
+                        execute_task(&send, &mut count_map, &mut spawned, &pool, shared, next);
+                    }
+                }
+                Outcome::Done((_, new_tasks)) => taskq.extend(new_tasks),
+                Outcome::Failed(e) => {
+                    taskq.push_back(e.task.clone());
+                    failed(&mut count_map, &mut failure, e);
+                }
+                Outcome::Skipped(task) => {
+                    // the job may have seen the cancellation first
+                    cancelled = true;
+                    skipped(&mut count_map, &task);
+                    taskq.push_back(task);
+                }
+            }
+            peak_pending = peak_pending.max(spawned);
+            shared.pending(taskq.len(), spawned as usize);
+        }
+
+        RunSummary {
+            output,
+            count_map,
+            peak_pending: Some(peak_pending as usize),
+            unfinished: (cancelled || failure.is_some()).then_some(taskq.len()),
+            failure,
+            ..Default::default()
+        }
+    }
+
+    fn run_bounded(
+        &self,
+        initial: Vec<Task>,
+        max_pending: usize,
+        shared: &Arc<Shared>,
+    ) -> RunSummary {
+        let pool = ThreadPool::new(self.n_threads);
+
+        let (send, recv) = channel();
+
+        let mut count_map = HashMap::new();
+        // The initial set is drawn from one task at a time like a lazy set of
+        // siblings, so it only takes a single slot until it is used up.
+        let mut initial = initial.into_iter();
+        let mut taskq: VecDeque<LazyTask> = VecDeque::new();
+        let queued = |taskq: &VecDeque<LazyTask>, initial: &std::vec::IntoIter<Task>| {
+            taskq.len() + usize::from(initial.len() > 0)
+        };
+
+        let mut output: u64 = 0;
+        let mut spawned: usize = 0;
+        // the most tasks the jobs in flight can hand back
+        let mut reserved: usize = 0;
+        let mut peak_pending = queued(&taskq, &initial);
+        let mut cancelled = false;
+        let mut failure = None;
+
+        loop {
+            cancelled = cancelled || failure.is_some() || shared.cancelled();
+            // Every task taken from the queue must leave room for everything
+            // it can return, and for one more task per level below it which
+            // expanding its subtree lazily takes later on. It is expanded
+            // eagerly from the front of the queue if that fits, else lazily
+            // from the back (depth first). When nothing is in flight the
+            // newest task runs anyway, so that a limit which is too low still
+            // makes progress.
+            while !cancelled && spawned < self.n_threads {
+                let queued_now = queued(&taskq, &initial);
+                if queued_now == 0 {
+                    break;
+                }
+                // Taking a task from the initial set only frees its slot once
+                // the set is used up.
+                let fits = |from_initial: bool, returned: usize| {
+                    let left = queued_now - usize::from(!from_initial || initial.len() == 1);
+                    left + reserved + returned <= max_pending
+                };
+                // Oldest first when expanding eagerly, i.e. the initial set, and
+                // newest first when lazily.
+                let peek_initial = |initial: &std::vec::IntoIter<Task>| {
+                    initial.as_slice().first().cloned().map(LazyTask::from)
+                };
+                let oldest = peek_initial(&initial).or_else(|| taskq.front().cloned());
+                let newest = taskq.back().cloned().or_else(|| peek_initial(&initial));
+                let oldest = oldest.unwrap();
+                let eager = fits(
+                    initial.len() > 0,
+                    returned(&oldest, true) + oldest.task.height,
+                );
+                let newest = newest.unwrap();
+                let lazy_fits = fits(
+                    taskq.is_empty(),
+                    returned(&newest, false) + newest.task.height,
+                );
+                if !eager && !lazy_fits && spawned > 0 {
+                    break;
+                }
+                let next = if eager {
+                    initial
+                        .next()
+                        .map(LazyTask::from)
+                        .or_else(|| taskq.pop_front())
+                } else {
+                    taskq
+                        .pop_back()
+                        .or_else(|| initial.next().map(LazyTask::from))
+                };
+                let next = next.unwrap();
+                let send = send.clone();
+                let shared_job = shared.clone();
+                *count_map.entry(next.task.typ).or_insert(0usize) += 1;
+                spawned += 1;
+                let reserve = returned(&next, eager).max(1);
+                reserved += reserve;
+                shared.dispatched(&next.task);
+                pool.execute(move || {
+                    let result =
+                        shared_job.attempt(&next.task, || expand(&next, eager, &shared_job));
+                    send.send((reserve, result.map_err(|failure| (next, failure))))
+                        .unwrap();
+                });
+            }
+
+            shared.pending(queued(&taskq, &initial), spawned);
+
+            if spawned == 0 {
+                break;
+            }
+
+            let (reserve, result) = recv.recv().unwrap();
+            spawned -= 1;
+            reserved -= reserve;
+            match result {
+                Ok((result, new_tasks)) => {
+                    output ^= result;
+                    taskq.extend(new_tasks);
+                }
+                Err((next, e)) => {
+                    taskq.push_back(next);
+                    failed(&mut count_map, &mut failure, e);
+                }
+            }
+            peak_pending = peak_pending.max(queued(&taskq, &initial) + spawned);
+        }
+
+        RunSummary {
+            output,
+            count_map,
+            peak_pending: Some(peak_pending),
+            // every queued task still stands for the siblings after it
+            unfinished: cancelled
+                .then(|| taskq.iter().map(|t| 1 + t.siblings.len()).sum::<usize>() + initial.len()),
+            failure,
+            ..Default::default()
+        }
+    }
+
+    fn run_prioritized(
+        &self,
+        initial: Vec<Task>,
+        policy: Policy,
+        shared: &Arc<Shared>,
+    ) -> RunSummary {
+        let pool = ThreadPool::new(self.n_threads);
+
+        let (send, recv) = channel();
+
+        let mut count_map = HashMap::new();
+        let mut ready = ReadyQueue::new(policy);
+        ready.extend(initial);
+
+        let mut output: u64 = 0;
+        let mut spawned: u64 = 0;
+        let mut peak_pending = ready.len();
+        let mut cancelled = false;
+        let mut failure = None;
+
+        loop {
+            cancelled = cancelled || failure.is_some() || shared.cancelled();
+            // Anything handed to the pool runs in FIFO order, so keep the rest
+            // in the ready queue until a worker is free.
+            while !cancelled && spawned < self.n_threads as u64 {
+                match ready.pop() {
+                    Some(next) => {
+                        execute_task(&send, &mut count_map, &mut spawned, &pool, shared, next)
+                    }
+                    None => break,
+                }
+            }
+
+            shared.pending(ready.len(), spawned as usize);
+
+            if spawned == 0 {
+                break;
+            }
+
+            match wait_task(&recv, &mut spawned, &mut output) {
+                Outcome::Done((_, new_tasks)) => ready.extend(new_tasks),
+                Outcome::Failed(e) => {
+                    ready.push(e.task.clone());
+                    failed(&mut count_map, &mut failure, e);
+                }
+                Outcome::Skipped(task) => {
+                    cancelled = true;
+                    skipped(&mut count_map, &task);
+                    ready.push(task);
+                }
+            }
+            peak_pending = peak_pending.max(ready.len() + spawned as usize);
+        }
+
+        RunSummary {
+            output,
+            count_map,
+            peak_pending: Some(peak_pending),
+            unfinished: cancelled.then_some(ready.len()),
+            failure,
+            ..Default::default()
+        }
+    }
+
+    fn run_batched(&self, initial: Vec<Task>, shared: &Arc<Shared>) -> RunSummary {
+        let pool = ThreadPool::new(self.n_threads);
+
+        let (send, recv) = channel::<Vec<Outcome>>();
+
+        let mut count_map = HashMap::new();
+        let mut taskq = VecDeque::from(initial);
+
+        let mut output: u64 = 0;
+        // batches in the pool, and the tasks in them
+        let mut batches: usize = 0;
+        let mut spawned: usize = 0;
+        let mut peak_pending = taskq.len();
+        let mut cancelled = false;
+        let mut failure = None;
+
+        loop {
+            cancelled = cancelled || failure.is_some() || shared.cancelled();
+            // Two batches per thread keep the workers busy while the results
+            // of the other one are handled here.
+            while !cancelled && !taskq.is_empty() && batches < 2 * self.n_threads {
+                let size = batch_size(taskq.len(), self.n_threads);
+                let batch: Vec<Task> = taskq.drain(..size).collect();
+                for next in &batch {
+                    *count_map.entry(next.typ).or_insert(0usize) += 1;
+                }
+                shared.dispatched(&batch[batch.len() - 1]);
+                batches += 1;
+                spawned += batch.len();
+                let send = send.clone();
+                let shared_job = shared.clone();
+                pool.execute(move || {
+                    let outcomes = batch.into_iter().map(|next| shared_job.run(next));
+                    send.send(outcomes.collect()).unwrap();
+                });
+            }
+
+            shared.pending(taskq.len(), spawned);
+
+            if batches == 0 {
+                break;
+            }
+
+            let outcomes = recv.recv().unwrap();
+            batches -= 1;
+            spawned -= outcomes.len();
+            for outcome in outcomes {
+                match outcome {
+                    Outcome::Done((result, new_tasks)) => {
+                        output ^= result;
+                        taskq.extend(new_tasks);
+                    }
+                    Outcome::Failed(e) => {
+                        taskq.push_back(e.task.clone());
+                        failed(&mut count_map, &mut failure, e);
+                    }
+                    Outcome::Skipped(task) => {
+                        cancelled = true;
+                        skipped(&mut count_map, &task);
+                        taskq.push_back(task);
+                    }
+                }
+            }
+            peak_pending = peak_pending.max(taskq.len() + spawned);
+        }
+
+        RunSummary {
+            output,
+            count_map,
+            peak_pending: Some(peak_pending),
+            unfinished: cancelled.then_some(taskq.len()),
+            failure,
+            ..Default::default()
+        }
+    }
+}
+
+impl Scheduler for ThreadPoolChannel {
+    fn run(&self, initial: Vec<Task>) -> RunSummary {
+        let shared = Arc::new(Shared {
+            recorder: TimingRecorder::new(),
+            cache: self.cache.clone(),
+            progress: self.progress.map(|_| Arc::new(Progress::new())),
+            top_height: initial.iter().map(|task| task.height).max().unwrap_or(0),
+            retries: self.retries,
+            cancel: self.cancel.clone(),
+        });
+        let cache_before = self.cache.as_ref().map(|cache| cache.stats());
+        let reporter = match (&shared.progress, self.progress) {
+            (Some(progress), Some(interval)) => {
+                Some(ProgressReporter::start(progress.clone(), interval))
+            }
+            _ => None,
+        };
+
+        let mut summary = match (self.max_pending, self.policy) {
+            (Some(max_pending), _) => self.run_bounded(initial, max_pending, &shared),
+            (None, Some(policy)) => self.run_prioritized(initial, policy, &shared),
+            (None, None) if self.batching => self.run_batched(initial, &shared),
+            (None, None) => self.run_unbounded(initial, &shared),
+        };
+        drop(reporter);
+
+        summary.timings = shared.recorder.summarize();
+        if let (Some(cache), Some(before)) = (&self.cache, cache_before) {
+            if let Err(e) = cache.flush() {
+                eprintln!(
+                    "warning: failed to write to cache {}: {}",
+                    cache.path().display(),
+                    e
+                );
+            }
+            summary.cache = Some(cache.stats().since(before));
+        }
+        summary
+    }
+}
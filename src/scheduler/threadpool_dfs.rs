@@ -0,0 +1,80 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::mpsc::channel,
+    sync::Arc,
+};
+
+use threadpool::ThreadPool;
+
+use super::{RunSummary, Scheduler};
+use crate::{lazy::LazyTask, stats::TimingRecorder, task::Task};
+
+/// Depth first over lazily generated siblings, dispatching at most one task
+/// per iteration and polling for results with `try_recv`.
+pub struct ThreadPoolDfs {
+    n_threads: usize,
+}
+
+impl ThreadPoolDfs {
+    pub fn new(n_threads: usize) -> Self {
+        ThreadPoolDfs { n_threads }
+    }
+}
+
+impl Scheduler for ThreadPoolDfs {
+    fn run(&self, initial: Vec<Task>) -> RunSummary {
+        let mut count_map = HashMap::new();
+        let mut taskq: VecDeque<LazyTask> = initial.into_iter().map(LazyTask::from).collect();
+
+        let mut task_counter = taskq.len() as u128;
+
+        let (send_ch, recv_ch) = channel();
+        let th_pool = ThreadPool::new(self.n_threads);
+        let recorder = Arc::new(TimingRecorder::new());
+
+        let mut output: u64 = 0;
+
+        while task_counter > 0 {
+            /*
+            In every step do the following:
+            1. get the next task if any
+            2. If there is, send is to task pool
+            3. Attempt to recv any result
+
+            Repeat this until all the tasks are finished
+            */
+            if let Some(next) = taskq.pop_front() {
+                *count_map.entry(next.task.typ).or_insert(0usize) += 1;
+                let send_ch = send_ch.clone();
+                let recorder = recorder.clone();
+                th_pool.execute(move || {
+                    send_ch
+                        .send(recorder.time(next.task.typ, || next.execute()))
+                        .expect("Please receive this task");
+                })
+            }
+
+            if let Ok((result, child_task, sibling_task)) = recv_ch.try_recv() {
+                if let Some(sibling_task) = sibling_task {
+                    taskq.push_front(sibling_task);
+                    task_counter += 1;
+                }
+
+                if let Some(child_task) = child_task {
+                    taskq.push_front(child_task);
+                    task_counter += 1;
+                }
+
+                output ^= result;
+                task_counter -= 1;
+            }
+        }
+
+        RunSummary {
+            output,
+            count_map,
+            timings: recorder.summarize(),
+            ..Default::default()
+        }
+    }
+}
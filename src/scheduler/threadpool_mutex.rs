@@ -0,0 +1,54 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use threadpool::ThreadPool;
+
+use super::{RunSummary, Scheduler};
+use crate::{aggregate::Aggregator, stats::TimingRecorder, task::Task};
+
+/// Workers push the children straight into a shared queue behind a `Mutex`
+/// and record their output and type in an `Aggregator`. The calling thread
+/// drains the queue one level at a time, joining the pool in between.
+pub struct ThreadPoolMutex {
+    n_threads: usize,
+}
+
+impl ThreadPoolMutex {
+    pub fn new(n_threads: usize) -> Self {
+        ThreadPoolMutex { n_threads }
+    }
+}
+
+impl Scheduler for ThreadPoolMutex {
+    fn run(&self, initial: Vec<Task>) -> RunSummary {
+        let pool = ThreadPool::new(self.n_threads);
+
+        let taskq = Arc::new(Mutex::new(VecDeque::from(initial)));
+
+        let aggregator = Arc::new(Aggregator::new(self.n_threads));
+        let recorder = Arc::new(TimingRecorder::new());
+
+        while !taskq.lock().unwrap().is_empty() {
+            let mut tq = taskq.lock().unwrap();
+            while let Some(next) = tq.pop_front() {
+                let taskq = taskq.clone();
+                let aggregator = aggregator.clone();
+                let recorder = recorder.clone();
+                pool.execute(move || {
+                    let result = recorder.time(next.typ, || next.execute());
+                    aggregator.record(next.typ, result.0);
+                    taskq.lock().unwrap().extend(result.1);
+                });
+            }
+            drop(tq); // if tq is not dropped, the mutex will be held by the main thread, preventing write from worker threads
+            pool.join();
+        }
+
+        RunSummary {
+            timings: recorder.summarize(),
+            ..aggregator.tally().summary()
+        }
+    }
+}
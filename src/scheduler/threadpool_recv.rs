@@ -0,0 +1,112 @@
+use std::{
+    collections::HashMap,
+    sync::mpsc::{channel, Sender},
+    sync::Arc,
+};
+
+use threadpool::ThreadPool;
+
+use super::{RunSummary, Scheduler};
+use crate::{
+    lazy::{LazyTask, LazyTaskResult},
+    stats::TimingRecorder,
+    task::{Task, TaskType},
+};
+
+/// Blocks on every result and immediately sends the child and the next
+/// sibling of the finished task back to the pool.
+pub struct ThreadPoolRecv {
+    n_threads: usize,
+}
+
+impl ThreadPoolRecv {
+    pub fn new(n_threads: usize) -> Self {
+        ThreadPoolRecv { n_threads }
+    }
+}
+
+fn execute_task(
+    send_ch: &Sender<LazyTaskResult>,
+    count_map: &mut HashMap<TaskType, usize>,
+    task_counter: &mut u128,
+    th_pool: &ThreadPool,
+    recorder: &Arc<TimingRecorder>,
+    new_task: LazyTask,
+) {
+    *task_counter += 1;
+    *count_map.entry(new_task.task.typ).or_insert(0usize) += 1;
+    let send_ch = send_ch.clone();
+    let recorder = recorder.clone();
+    th_pool.execute(move || {
+        send_ch
+            .send(recorder.time(new_task.task.typ, || new_task.execute()))
+            .expect("Please receive this task");
+    });
+}
+
+impl Scheduler for ThreadPoolRecv {
+    fn run(&self, initial: Vec<Task>) -> RunSummary {
+        let mut count_map = HashMap::new();
+
+        let mut task_counter: u128 = 0;
+
+        let (send_ch, recv_ch) = channel();
+        let th_pool = ThreadPool::new(self.n_threads);
+        let recorder = Arc::new(TimingRecorder::new());
+
+        let mut output: u64 = 0;
+
+        for init_task in initial {
+            execute_task(
+                &send_ch,
+                &mut count_map,
+                &mut task_counter,
+                &th_pool,
+                &recorder,
+                init_task.into(),
+            );
+        }
+
+        while task_counter > 0 {
+            /*
+            In every step do the following:
+            1. Wait for result from task pool
+            2. If there is more task, send it back to the pool
+
+            Repeat this until all the tasks are finished
+            */
+            let (result, child_task, sibling_task) = recv_ch.recv().unwrap();
+            if let Some(new_task) = child_task {
+                execute_task(
+                    &send_ch,
+                    &mut count_map,
+                    &mut task_counter,
+                    &th_pool,
+                    &recorder,
+                    new_task,
+                );
+            }
+
+            if let Some(new_task) = sibling_task {
+                execute_task(
+                    &send_ch,
+                    &mut count_map,
+                    &mut task_counter,
+                    &th_pool,
+                    &recorder,
+                    new_task,
+                );
+            }
+
+            output ^= result;
+            task_counter -= 1;
+        }
+
+        RunSummary {
+            output,
+            count_map,
+            timings: recorder.summarize(),
+            ..Default::default()
+        }
+    }
+}
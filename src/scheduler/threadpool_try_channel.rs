@@ -0,0 +1,68 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::mpsc::channel,
+    sync::Arc,
+};
+
+use threadpool::ThreadPool;
+
+use super::{RunSummary, Scheduler};
+use crate::{stats::TimingRecorder, task::Task};
+
+/// Like `ThreadPoolChannel`, but polls the channel with `try_recv` and
+/// dispatches whatever has been queued up in between.
+pub struct ThreadPoolTryChannel {
+    n_threads: usize,
+}
+
+impl ThreadPoolTryChannel {
+    pub fn new(n_threads: usize) -> Self {
+        ThreadPoolTryChannel { n_threads }
+    }
+}
+
+impl Scheduler for ThreadPoolTryChannel {
+    fn run(&self, initial: Vec<Task>) -> RunSummary {
+        let pool = ThreadPool::new(self.n_threads);
+
+        let (send, recv) = channel();
+        let recorder = Arc::new(TimingRecorder::new());
+
+        let mut count_map = HashMap::new();
+        let mut taskq = VecDeque::from(initial);
+
+        let mut output: u64 = 0;
+        let mut spawned: u64 = 0;
+
+        while !taskq.is_empty() || spawned > 0 {
+            while let Some(next) = taskq.pop_front() {
+                let send = send.clone();
+                let recorder = recorder.clone();
+                *count_map.entry(next.typ).or_insert(0usize) += 1;
+                spawned += 1;
+                pool.execute(move || {
+                    send.send(recorder.time(next.typ, || next.execute()))
+                        .unwrap();
+                });
+            }
+
+            while spawned > 0 {
+                match recv.try_recv() {
+                    Ok(result) => {
+                        spawned -= 1;
+                        output ^= result.0;
+                        taskq.extend(result.1);
+                    }
+                    Err(_) => break,
+                };
+            }
+        }
+
+        RunSummary {
+            output,
+            count_map,
+            timings: recorder.summarize(),
+            ..Default::default()
+        }
+    }
+}
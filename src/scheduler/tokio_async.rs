@@ -0,0 +1,79 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use super::{RunSummary, Scheduler};
+use crate::{stats::TimingRecorder, task::Task};
+
+/// Runs every task with `spawn_blocking`, so the task bodies never block the
+/// async runtime, with a semaphore letting at most `n_threads` of them run at
+/// once. Results are handled in the order they complete and their children
+/// are spawned right away.
+pub struct TokioAsync {
+    n_threads: usize,
+}
+
+impl TokioAsync {
+    pub fn new(n_threads: usize) -> Self {
+        // with no permits at all nothing would ever run
+        TokioAsync {
+            n_threads: n_threads.max(1),
+        }
+    }
+}
+
+impl Scheduler for TokioAsync {
+    fn run(&self, initial: Vec<Task>) -> RunSummary {
+        // Only dispatching happens on the runtime itself, the calling thread
+        // is enough for that.
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .max_blocking_threads(self.n_threads)
+            .build()
+            .expect("failed to build tokio runtime");
+
+        runtime.block_on(async move {
+            let mut count_map = HashMap::new();
+            let mut taskq = VecDeque::from(initial);
+            let mut running = JoinSet::new();
+            let permits = Arc::new(Semaphore::new(self.n_threads));
+            let recorder = Arc::new(TimingRecorder::new());
+
+            let mut output: u64 = 0;
+
+            loop {
+                while let Some(next) = taskq.pop_front() {
+                    *count_map.entry(next.typ).or_insert(0usize) += 1;
+                    let permits = permits.clone();
+                    let recorder = recorder.clone();
+                    running.spawn(async move {
+                        let _permit = permits.acquire_owned().await.unwrap();
+                        tokio::task::spawn_blocking(move || {
+                            recorder.time(next.typ, || next.execute())
+                        })
+                        .await
+                        .unwrap()
+                    });
+                }
+
+                match running.join_next().await {
+                    Some(result) => {
+                        let (result, children) = result.unwrap();
+                        output ^= result;
+                        taskq.extend(children);
+                    }
+                    None => break,
+                }
+            }
+
+            RunSummary {
+                output,
+                count_map,
+                timings: recorder.summarize(),
+                ..Default::default()
+            }
+        })
+    }
+}
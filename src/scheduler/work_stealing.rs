@@ -0,0 +1,132 @@
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicUsize, Ordering},
+    sync::{Condvar, Mutex},
+    thread,
+};
+
+use super::{RunSummary, Scheduler};
+use crate::{aggregate::Tally, stats::TimingRecorder, task::Task};
+
+/// Every worker owns a deque. Children are pushed onto the back of the deque
+/// of the worker that produced them and popped from the back again (depth
+/// first), while idle workers steal from the front of the other deques.
+///
+/// There is no central thread: `outstanding` counts the tasks that are queued
+/// or running, and every worker stops once it drops to zero. Workers which
+/// find nothing to steal sleep until new tasks are pushed.
+pub struct WorkStealing {
+    n_threads: usize,
+}
+
+impl WorkStealing {
+    pub fn new(n_threads: usize) -> Self {
+        WorkStealing {
+            n_threads: n_threads.max(1),
+        }
+    }
+}
+
+struct Shared {
+    deques: Vec<Mutex<VecDeque<Task>>>,
+    outstanding: AtomicUsize,
+    recorder: TimingRecorder,
+    idle: Mutex<()>,
+    wakeup: Condvar,
+}
+
+impl Shared {
+    fn pop_local(&self, id: usize) -> Option<Task> {
+        self.deques[id].lock().unwrap().pop_back()
+    }
+
+    fn steal(&self, id: usize) -> Option<Task> {
+        let n = self.deques.len();
+        (1..n).find_map(|offset| self.deques[(id + offset) % n].lock().unwrap().pop_front())
+    }
+
+    /// The next task for worker `id`, waiting for one to be pushed if all
+    /// deques are empty. `None` once nothing is outstanding any more.
+    fn next(&self, id: usize) -> Option<Task> {
+        loop {
+            if let Some(next) = self.pop_local(id).or_else(|| self.steal(id)) {
+                return Some(next);
+            }
+            let idle = self.idle.lock().unwrap();
+            // Checked again under the lock, which `wake` takes after pushing,
+            // so a push cannot slip in between the check and the wait.
+            if self.outstanding.load(Ordering::Acquire) == 0 {
+                return None;
+            }
+            if let Some(next) = self.pop_local(id).or_else(|| self.steal(id)) {
+                return Some(next);
+            }
+            drop(self.wakeup.wait(idle).unwrap());
+        }
+    }
+
+    fn wake(&self) {
+        let _idle = self.idle.lock().unwrap();
+        self.wakeup.notify_all();
+    }
+
+    fn worker(&self, id: usize) -> Tally {
+        let mut tally = Tally::default();
+
+        while let Some(next) = self.next(id) {
+            let (result, children) = self.recorder.time(next.typ, || next.execute());
+            tally.record(next.typ, result);
+
+            // Children must be accounted for before this task is retired,
+            // otherwise the other workers could see zero and stop early.
+            let pushed = !children.is_empty();
+            self.outstanding.fetch_add(children.len(), Ordering::AcqRel);
+            self.deques[id].lock().unwrap().extend(children);
+            let last = self.outstanding.fetch_sub(1, Ordering::AcqRel) == 1;
+            if pushed || last {
+                self.wake();
+            }
+        }
+
+        tally
+    }
+}
+
+impl Scheduler for WorkStealing {
+    fn run(&self, initial: Vec<Task>) -> RunSummary {
+        let shared = Shared {
+            deques: (0..self.n_threads)
+                .map(|_| Mutex::new(VecDeque::new()))
+                .collect(),
+            outstanding: AtomicUsize::new(initial.len()),
+            recorder: TimingRecorder::new(),
+            idle: Mutex::new(()),
+            wakeup: Condvar::new(),
+        };
+        for (idx, task) in initial.into_iter().enumerate() {
+            shared.deques[idx % self.n_threads]
+                .lock()
+                .unwrap()
+                .push_back(task);
+        }
+
+        let partials: Vec<Tally> = thread::scope(|s| {
+            let handles: Vec<_> = (0..self.n_threads)
+                .map(|id| {
+                    let shared = &shared;
+                    s.spawn(move || shared.worker(id))
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        let mut tally = Tally::default();
+        for partial in &partials {
+            tally.merge(partial);
+        }
+        RunSummary {
+            timings: shared.recorder.summarize(),
+            ..tally.summary()
+        }
+    }
+}
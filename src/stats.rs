@@ -0,0 +1,153 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::task::{TaskType, MAX_KINDS};
+
+/// The largest resident set size of this process so far, in bytes.
+#[cfg(target_os = "linux")]
+pub fn peak_rss_bytes() -> Option<u64> {
+    // SAFETY: `getrusage` only writes into the zeroed struct we hand it.
+    let usage = unsafe {
+        let mut usage: libc::rusage = std::mem::zeroed();
+        if libc::getrusage(libc::RUSAGE_SELF, &mut usage) != 0 {
+            return None;
+        }
+        usage
+    };
+    // `ru_maxrss` is in kilobytes on linux
+    Some(usage.ru_maxrss as u64 * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn peak_rss_bytes() -> Option<u64> {
+    None
+}
+
+/// Latency distribution of every executed task of one `TaskType`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    pub count: usize,
+    pub min: Duration,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+    /// Sum of all samples, i.e. the CPU time spent on this type.
+    pub total: Duration,
+}
+
+impl LatencyStats {
+    fn from_samples(mut samples: Vec<Duration>) -> Option<LatencyStats> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        let count = samples.len();
+        let total: Duration = samples.iter().sum();
+        Some(LatencyStats {
+            count,
+            min: samples[0],
+            // in nanoseconds, since dividing a `Duration` needs a u32 count
+            mean: Duration::from_nanos((total.as_nanos() / count as u128) as u64),
+            p50: percentile(&samples, 0.50),
+            p99: percentile(&samples, 0.99),
+            max: samples[count - 1],
+            total,
+        })
+    }
+}
+
+/// The nearest-rank percentile `p` (between 0 and 1) of non-empty `sorted`.
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let count = sorted.len();
+    sorted[((p * count as f64).ceil() as usize).clamp(1, count) - 1]
+}
+
+/// Collects how long every task took, from any number of worker threads.
+#[derive(Debug, Default)]
+pub struct TimingRecorder {
+    samples: [Mutex<Vec<Duration>>; MAX_KINDS],
+}
+
+impl TimingRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `f`, which executes a task of type `typ`, and records its duration.
+    pub fn time<T>(&self, typ: TaskType, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record(typ, start.elapsed());
+        result
+    }
+
+    pub fn record(&self, typ: TaskType, elapsed: Duration) {
+        self.samples[typ.index()].lock().unwrap().push(elapsed);
+    }
+
+    pub fn summarize(&self) -> HashMap<TaskType, LatencyStats> {
+        TaskType::all()
+            .into_iter()
+            .filter_map(|typ| {
+                let samples = std::mem::take(&mut *self.samples[typ.index()].lock().unwrap());
+                LatencyStats::from_samples(samples).map(|stats| (typ, stats))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(values: &[u64]) -> Vec<Duration> {
+        values.iter().map(|&v| Duration::from_millis(v)).collect()
+    }
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let samples = ms(&(1..=100).collect::<Vec<_>>());
+        assert_eq!(percentile(&samples, 0.0), Duration::from_millis(1));
+        assert_eq!(percentile(&samples, 0.5), Duration::from_millis(50));
+        assert_eq!(percentile(&samples, 0.99), Duration::from_millis(99));
+        assert_eq!(percentile(&samples, 1.0), Duration::from_millis(100));
+
+        let samples = ms(&[10, 20, 30]);
+        assert_eq!(percentile(&samples, 0.5), Duration::from_millis(20));
+        assert_eq!(percentile(&samples, 0.99), Duration::from_millis(30));
+    }
+
+    #[test]
+    fn a_single_sample_is_every_percentile() {
+        let stats = LatencyStats::from_samples(ms(&[7])).unwrap();
+        let seven = Duration::from_millis(7);
+        assert_eq!(
+            (stats.min, stats.mean, stats.p50, stats.p99, stats.max),
+            (seven, seven, seven, seven, seven)
+        );
+        assert_eq!(stats.count, 1);
+        assert_eq!(stats.total, seven);
+    }
+
+    #[test]
+    fn no_samples_no_stats() {
+        assert_eq!(LatencyStats::from_samples(Vec::new()), None);
+    }
+
+    #[test]
+    fn mean_and_total() {
+        let stats = LatencyStats::from_samples(ms(&[1, 2])).unwrap();
+        assert_eq!(stats.mean, Duration::from_micros(1500));
+        let stats = LatencyStats::from_samples(ms(&[30, 10, 20, 40])).unwrap();
+        assert_eq!(stats.mean, Duration::from_millis(25));
+        assert_eq!(stats.total, Duration::from_millis(100));
+        assert_eq!(
+            (stats.min, stats.max),
+            (Duration::from_millis(10), Duration::from_millis(40))
+        );
+    }
+}
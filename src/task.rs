@@ -1,16 +1,171 @@
-// Do not modify this file.
+use std::{
+    error::Error,
+    fmt,
+    sync::{OnceLock, RwLock},
+};
+
 use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
 
 pub type TaskResult = (u64, Vec<Task>);
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
-pub enum TaskType {
-    Hash,
-    Derive,
-    Random,
+/// The most task kinds that can be registered, built-in ones included.
+pub const MAX_KINDS: usize = 16;
+
+/// A kind of work a task can do. Hash, derive and random are always
+/// registered, others can be added with `register_kind`.
+pub trait TaskKind: Send + Sync {
+    /// Identifies the kind in checkpoints and reports, so it must be unique.
+    fn name(&self) -> &str;
+
+    /// How likely a new task is of this kind, relative to the weights of the
+    /// other kinds. The built-in kinds weigh 1 each, and a kind weighing 0
+    /// is refused by `register_kind`.
+    fn weight(&self) -> u32 {
+        1
+    }
+
+    /// The body of a task with this `seed`. It has to be deterministic, since
+    /// the output seeds the children of the task.
+    fn execute(&self, seed: u64) -> u64;
+
+    /// How much work `execute` does for `seed`, without running it. Only used
+    /// for estimates, so kinds which cannot tell may leave it at 0.
+    fn rounds(&self, _seed: u64) -> usize {
+        0
+    }
 }
 
-static TYPE_ARRAY: [TaskType; 3] = [TaskType::Hash, TaskType::Derive, TaskType::Random];
+/// A registered `TaskKind`, by its position in the registry.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TaskType(u8);
+
+#[allow(non_upper_case_globals)]
+impl TaskType {
+    pub const Hash: TaskType = TaskType(0);
+    pub const Derive: TaskType = TaskType(1);
+    pub const Random: TaskType = TaskType(2);
+
+    /// Position of this type in the registry, for indexing per-type arrays
+    /// of `MAX_KINDS` entries.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    /// The type at `index`, if that many kinds are registered.
+    pub fn from_index(index: usize) -> Option<TaskType> {
+        (index < registry().read().unwrap().len()).then_some(TaskType(index as u8))
+    }
+
+    /// Every registered type, the built-in ones first.
+    pub fn all() -> Vec<TaskType> {
+        (0..registry().read().unwrap().len())
+            .map(|index| TaskType(index as u8))
+            .collect()
+    }
+
+    pub fn kind(self) -> &'static dyn TaskKind {
+        registry().read().unwrap()[self.index()]
+    }
+
+    pub fn name(self) -> &'static str {
+        self.kind().name()
+    }
+
+    /// Draws the type of a new task from `rng`, with the probability of each
+    /// type given by its weight. With only the built-in kinds this is the
+    /// same single draw out of three as before kinds were pluggable.
+    fn choose(rng: &mut impl Rng) -> TaskType {
+        let kinds = registry().read().unwrap();
+        // asked once, so the pick is always drawn against the weights used
+        let mut weights = [0; MAX_KINDS];
+        for (weight, kind) in weights.iter_mut().zip(kinds.iter()) {
+            *weight = kind.weight() as usize;
+        }
+        let weights = &weights[..kinds.len()];
+        // never empty, since every registered kind weighs at least 1
+        let mut pick = rng.gen_range(0..weights.iter().sum::<usize>());
+        for (index, &weight) in weights.iter().enumerate() {
+            match pick.checked_sub(weight) {
+                Some(rest) => pick = rest,
+                None => return TaskType(index as u8),
+            }
+        }
+        unreachable!("the pick is below the total weight")
+    }
+}
+
+impl fmt::Debug for TaskType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Why `register_kind` refused a kind.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RegisterError {
+    DuplicateName(String),
+    TooManyKinds,
+    /// A kind of weight 0 could never be drawn.
+    ZeroWeight(String),
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegisterError::DuplicateName(name) => {
+                write!(f, "a task kind named '{}' is already registered", name)
+            }
+            RegisterError::TooManyKinds => {
+                write!(f, "no more than {} task kinds can be registered", MAX_KINDS)
+            }
+            RegisterError::ZeroWeight(name) => {
+                write!(f, "task kind '{}' has a weight of 0", name)
+            }
+        }
+    }
+}
+
+impl Error for RegisterError {}
+
+fn registry() -> &'static RwLock<Vec<&'static dyn TaskKind>> {
+    static KINDS: OnceLock<RwLock<Vec<&'static dyn TaskKind>>> = OnceLock::new();
+    KINDS.get_or_init(|| RwLock::new(vec![&HashKind, &DeriveKind, &RandomKind]))
+}
+
+/// Adds `kind` to the kinds new tasks are drawn from, for the rest of the
+/// process, and returns its type.
+///
+/// This changes the shape of every tree generated afterwards. Anything else
+/// working on the same tree, like remote workers, a resumed checkpoint or an
+/// output cache, has to see the same kinds registered in the same order.
+pub fn register_kind(kind: impl TaskKind + 'static) -> Result<TaskType, RegisterError> {
+    let mut kinds = registry().write().unwrap();
+    if kinds.iter().any(|known| known.name() == kind.name()) {
+        return Err(RegisterError::DuplicateName(kind.name().to_string()));
+    }
+    if kinds.len() == MAX_KINDS {
+        return Err(RegisterError::TooManyKinds);
+    }
+    if kind.weight() == 0 {
+        return Err(RegisterError::ZeroWeight(kind.name().to_string()));
+    }
+    kinds.push(Box::leak(Box::new(kind)));
+    Ok(TaskType(kinds.len() as u8 - 1))
+}
+
+/// Identifies the registered kinds, their order and their weights. Formats
+/// which refer to kinds by their index, like the output cache and the worker
+/// protocol, carry it so that they are never read with other kinds.
+pub fn fingerprint() -> u64 {
+    let mut ctx = ring::digest::Context::new(&ring::digest::SHA256);
+    for kind in registry().read().unwrap().iter() {
+        ctx.update(&(kind.name().len() as u64).to_le_bytes());
+        ctx.update(kind.name().as_bytes());
+        ctx.update(&kind.weight().to_le_bytes());
+    }
+    u64::from_le_bytes(ctx.finish().as_ref()[..8].try_into().unwrap())
+}
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Task {
@@ -20,83 +175,182 @@ pub struct Task {
     pub max_children: usize,
 }
 
-fn generate_set(seed: u64, height: usize, max_children: usize, max_num: usize) -> Vec<Task> {
-    let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(seed);
-    let num_tasks: usize = rng.gen_range(0..=max_num);
-    (0..num_tasks)
-        .map(|_| Task {
-            typ: TYPE_ARRAY[rng.gen_range(0..TYPE_ARRAY.len())],
-            seed: rng.gen(),
-            height,
-            max_children,
+/// A set of sibling tasks which is generated one task at a time. Every task
+/// is drawn from the same rng as in an eager `generate_set`, so the sequence
+/// is identical, but only the rng state has to be kept around.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Children {
+    rng: ChaCha20Rng,
+    remaining: usize,
+    height: usize,
+    max_children: usize,
+}
+
+impl Children {
+    /// A set without any tasks.
+    pub fn empty() -> Children {
+        Children {
+            rng: ChaCha20Rng::seed_from_u64(0),
+            remaining: 0,
+            height: 0,
+            max_children: 0,
+        }
+    }
+}
+
+impl Iterator for Children {
+    type Item = Task;
+
+    fn next(&mut self) -> Option<Task> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        Some(Task {
+            typ: TaskType::choose(&mut self.rng),
+            seed: self.rng.gen(),
+            height: self.height,
+            max_children: self.max_children,
         })
-        .collect()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for Children {}
+
+fn generate_set(seed: u64, height: usize, max_children: usize, max_num: usize) -> Children {
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    let num_tasks: usize = rng.gen_range(0..=max_num);
+    Children {
+        rng,
+        remaining: num_tasks,
+        height,
+        max_children,
+    }
 }
 
 impl Task {
     pub fn execute(&self) -> TaskResult {
-        let output = match self.typ {
-            TaskType::Hash => do_hash(self),
-            TaskType::Derive => do_derive(self),
-            TaskType::Random => do_random(self),
-        };
-        (
-            output,
-            if self.height == 0 {
-                Vec::new()
-            } else {
-                generate_set(
-                    self.seed ^ output,
-                    self.height - 1,
-                    self.max_children,
-                    self.max_children,
-                )
-            },
-        )
+        let output = self.compute();
+        (output, self.children(output).collect())
+    }
+
+    /// The children of this task given the `output` of its body, generated
+    /// lazily in the same order `execute` returns them.
+    pub fn children(&self, output: u64) -> Children {
+        if self.height == 0 {
+            Children::empty()
+        } else {
+            generate_set(
+                self.seed ^ output,
+                self.height - 1,
+                self.max_children,
+                self.max_children,
+            )
+        }
+    }
+
+    /// Runs the body of the task only, without generating its children.
+    pub fn compute(&self) -> u64 {
+        self.typ.kind().execute(self.seed)
+    }
+
+    /// How much work the body of this task does, see `TaskKind::rounds`.
+    pub fn rounds(&self) -> usize {
+        self.typ.kind().rounds(self.seed)
     }
 
     pub fn generate_initial(seed: u64, starting_height: usize, max_children: usize) -> Vec<Task> {
+        Task::initial_children(seed, starting_height, max_children).collect()
+    }
+
+    /// The lazy counterpart of `generate_initial`.
+    pub fn initial_children(seed: u64, starting_height: usize, max_children: usize) -> Children {
         generate_set(seed, starting_height, max_children, 64)
     }
 }
 
-fn do_hash(task: &Task) -> u64 {
-    let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(task.seed);
-    let rounds: usize = rng.gen_range(0x10000..0x20000);
-    let mut state: [u8; 32] = [0; 32];
-    rng.fill_bytes(&mut state);
+// Rounds of SHA-256 over a random state.
+struct HashKind;
+
+impl TaskKind for HashKind {
+    fn name(&self) -> &str {
+        "hash"
+    }
+
+    fn execute(&self, seed: u64) -> u64 {
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        let rounds: usize = rng.gen_range(0x10000..0x20000);
+        let mut state: [u8; 32] = [0; 32];
+        rng.fill_bytes(&mut state);
+
+        for _ in 0..rounds {
+            let result = ring::digest::digest(&ring::digest::SHA256, &state);
+            state.copy_from_slice(result.as_ref());
+        }
 
-    for _ in 0..rounds {
-        let result = ring::digest::digest(&ring::digest::SHA256, &state);
-        state.copy_from_slice(result.as_ref());
+        let take_from = rng.gen_range(0..(state.len() - std::mem::size_of::<u64>()));
+        u64::from_le_bytes(state[take_from..take_from + 8].try_into().unwrap())
     }
 
-    let take_from = rng.gen_range(0..(state.len() - std::mem::size_of::<u64>()));
-    u64::from_le_bytes(state[take_from..take_from + 8].try_into().unwrap())
+    fn rounds(&self, seed: u64) -> usize {
+        ChaCha20Rng::seed_from_u64(seed).gen_range(0x10000..0x20000)
+    }
 }
 
-fn do_derive(task: &Task) -> u64 {
-    let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(task.seed);
-    let mut state: [u8; 64] = [0; 64];
-    let mut out: [u8; 64] = [0; 64];
-    rng.fill_bytes(&mut state);
-    ring::pbkdf2::derive(
-        ring::pbkdf2::PBKDF2_HMAC_SHA512,
-        rng.gen_range(0x10000..0x20000).try_into().unwrap(),
-        &state[..32],
-        &state[32..],
-        &mut out[..],
-    );
+// PBKDF2-HMAC-SHA512 key derivation from a random password and salt.
+struct DeriveKind;
+
+impl TaskKind for DeriveKind {
+    fn name(&self) -> &str {
+        "derive"
+    }
+
+    fn execute(&self, seed: u64) -> u64 {
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        let mut state: [u8; 64] = [0; 64];
+        let mut out: [u8; 64] = [0; 64];
+        rng.fill_bytes(&mut state);
+        ring::pbkdf2::derive(
+            ring::pbkdf2::PBKDF2_HMAC_SHA512,
+            rng.gen_range(0x10000..0x20000).try_into().unwrap(),
+            &state[..32],
+            &state[32..],
+            &mut out[..],
+        );
 
-    let take_from = rng.gen_range(0..(out.len() - std::mem::size_of::<u64>()));
-    u64::from_le_bytes(out[take_from..take_from + 8].try_into().unwrap())
+        let take_from = rng.gen_range(0..(out.len() - std::mem::size_of::<u64>()));
+        u64::from_le_bytes(out[take_from..take_from + 8].try_into().unwrap())
+    }
+
+    fn rounds(&self, seed: u64) -> usize {
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        rng.fill_bytes(&mut [0; 64]);
+        rng.gen_range(0x10000u32..0x20000) as usize
+    }
 }
 
-fn do_random(task: &Task) -> u64 {
-    let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(task.seed);
-    let rounds: usize = rng.gen_range(0x10000..0x20000);
-    for _ in 0..rounds {
-        rng.gen::<u64>();
+// Draws from the rng itself.
+struct RandomKind;
+
+impl TaskKind for RandomKind {
+    fn name(&self) -> &str {
+        "random"
+    }
+
+    fn execute(&self, seed: u64) -> u64 {
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        let rounds: usize = rng.gen_range(0x10000..0x20000);
+        for _ in 0..rounds {
+            rng.gen::<u64>();
+        }
+        rng.gen()
+    }
+
+    fn rounds(&self, seed: u64) -> usize {
+        ChaCha20Rng::seed_from_u64(seed).gen_range(0x10000..0x20000)
     }
-    rng.gen()
 }
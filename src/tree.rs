@@ -0,0 +1,309 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    sync::mpsc::channel,
+};
+
+use threadpool::ThreadPool;
+
+use crate::{
+    cache::OutputCache,
+    task::{Task, TaskType, MAX_KINDS},
+};
+
+/// One executed task and where it sits in the tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TreeNode {
+    pub id: usize,
+    /// `None` for the tasks of the initial set.
+    pub parent: Option<usize>,
+    /// 0 for the tasks of the initial set.
+    pub depth: usize,
+    pub typ: TaskType,
+    pub seed: u64,
+    pub height: usize,
+    pub output: u64,
+}
+
+/// Executes the tree on `n_threads` threads and records every task, numbered
+/// breadth first with siblings in the order they were generated, so the ids
+/// do not depend on the threads. With `max_depth`, tasks deeper than that are
+/// neither executed nor recorded.
+pub fn explore(initial: Vec<Task>, n_threads: usize, max_depth: Option<usize>) -> Vec<TreeNode> {
+    let pool = ThreadPool::new(n_threads);
+    let (send, recv) = channel();
+
+    // Nodes get a provisional id when they are dispatched, and the sets of
+    // children of every node (the roots under `None`) by their position.
+    let mut nodes: Vec<Option<TreeNode>> = Vec::new();
+    let mut roots = Vec::new();
+    let mut children_of: Vec<Vec<usize>> = Vec::new();
+    let mut taskq: VecDeque<(Option<usize>, usize, Task)> =
+        initial.into_iter().map(|task| (None, 0, task)).collect();
+    let mut spawned = 0;
+
+    loop {
+        while let Some((parent, depth, task)) = taskq.pop_front() {
+            let id = nodes.len();
+            nodes.push(None);
+            children_of.push(Vec::new());
+            match parent {
+                Some(parent) => children_of[parent].push(id),
+                None => roots.push(id),
+            }
+            let send = send.clone();
+            spawned += 1;
+            pool.execute(move || {
+                let (output, children) = task.execute();
+                send.send((id, parent, depth, task, output, children))
+                    .unwrap();
+            });
+        }
+
+        if spawned == 0 {
+            break;
+        }
+
+        let (id, parent, depth, task, output, children) = recv.recv().unwrap();
+        spawned -= 1;
+        if max_depth.is_none_or(|max_depth| depth < max_depth) {
+            taskq.extend(
+                children
+                    .into_iter()
+                    .map(|child| (Some(id), depth + 1, child)),
+            );
+        }
+        nodes[id] = Some(TreeNode {
+            id,
+            parent,
+            depth,
+            typ: task.typ,
+            seed: task.seed,
+            height: task.height,
+            output,
+        });
+    }
+
+    // Breadth first from the roots gives the final ids.
+    let mut order = Vec::with_capacity(nodes.len());
+    let mut next: VecDeque<usize> = roots.into();
+    while let Some(id) = next.pop_front() {
+        next.extend(&children_of[id]);
+        order.push(id);
+    }
+    let mut final_id = vec![0; nodes.len()];
+    for (new, &old) in order.iter().enumerate() {
+        final_id[old] = new;
+    }
+    order
+        .into_iter()
+        .map(|old| {
+            let node = nodes[old].take().unwrap();
+            TreeNode {
+                id: final_id[old],
+                parent: node.parent.map(|parent| final_id[parent]),
+                ..node
+            }
+        })
+        .collect()
+}
+
+/// How many tasks of each type sit at one height of the tree.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LevelStats {
+    pub height: usize,
+    /// Indexed by `TaskType::index`.
+    pub counts: [usize; MAX_KINDS],
+}
+
+/// The shape of a whole tree, see `statistics`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TreeStats {
+    /// From the initial set downwards.
+    pub levels: Vec<LevelStats>,
+    /// Total rounds of the task bodies of each type, indexed by
+    /// `TaskType::index`.
+    pub rounds: [u64; MAX_KINDS],
+    /// The largest number of tasks on one level.
+    pub max_frontier: usize,
+    /// The XOR of all outputs, as a full run would print it.
+    pub output: u64,
+    /// How many outputs were found in the cache.
+    pub cached: usize,
+    /// How many task bodies had to be executed.
+    pub executed: usize,
+}
+
+impl TreeStats {
+    pub fn count(&self, typ: TaskType) -> usize {
+        self.levels
+            .iter()
+            .map(|level| level.counts[typ.index()])
+            .sum()
+    }
+}
+
+/// Walks the tree one level at a time. The output of every task is taken
+/// from `cache` if it is there, the rest are executed on `n_threads` threads
+/// and added to the cache, so asking again for the same tree is instant.
+pub fn statistics(
+    initial: Vec<Task>,
+    n_threads: usize,
+    cache: Option<&OutputCache>,
+) -> io::Result<TreeStats> {
+    let pool = ThreadPool::new(n_threads);
+    let (send, recv) = channel();
+
+    let mut stats = TreeStats::default();
+    let mut level = initial;
+
+    while !level.is_empty() {
+        let mut outputs: Vec<Option<u64>> = level
+            .iter()
+            .map(|task| cache.and_then(|cache| cache.get(task)))
+            .collect();
+        let mut spawned = 0;
+        for (idx, task) in level
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| outputs[*idx].is_none())
+        {
+            let send = send.clone();
+            let task = task.clone();
+            spawned += 1;
+            pool.execute(move || {
+                send.send((idx, task.compute())).unwrap();
+            });
+        }
+        stats.cached += level.len() - spawned;
+        stats.executed += spawned;
+        for _ in 0..spawned {
+            let (idx, output) = recv.recv().unwrap();
+            if let Some(cache) = cache {
+                cache.insert(&level[idx], output)?;
+            }
+            outputs[idx] = Some(output);
+        }
+
+        let mut level_stats = LevelStats {
+            height: level[0].height,
+            ..Default::default()
+        };
+        let mut next_level = Vec::new();
+        for (task, output) in level.iter().zip(outputs) {
+            let output = output.unwrap();
+            level_stats.counts[task.typ.index()] += 1;
+            stats.rounds[task.typ.index()] += task.rounds() as u64;
+            stats.output ^= output;
+            next_level.extend(task.children(output));
+        }
+        stats.max_frontier = stats.max_frontier.max(level.len());
+        stats.levels.push(level_stats);
+        level = next_level;
+    }
+
+    if let Some(cache) = cache {
+        cache.flush()?;
+    }
+    Ok(stats)
+}
+
+fn color(typ: TaskType) -> &'static str {
+    match typ {
+        TaskType::Hash => "#8dd3c7",
+        TaskType::Derive => "#fb8072",
+        TaskType::Random => "#ffffb3",
+        _ => "#d9d9d9",
+    }
+}
+
+/// Quotes `s` as a JSON string.
+pub fn json_str(s: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => escaped += "\\\"",
+            '\\' => escaped += "\\\\",
+            c if c.is_control() => escaped += &format!("\\u{:04x}", c as u32),
+            c => escaped.push(c),
+        }
+    }
+    escaped + "\""
+}
+
+/// Escapes `s` for a quoted DOT string, where a newline ends a label line.
+fn dot_escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '"' => escaped += "\\\"",
+            '\\' => escaped += "\\\\",
+            '\n' => escaped += "\\n",
+            c if c.is_control() => escaped.push(' '),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes the tree as a Graphviz digraph, with one node per task colored by
+/// its type, hanging off a `root` node for the initial set.
+pub fn write_dot(nodes: &[TreeNode], seed: u64, mut w: impl Write) -> io::Result<()> {
+    writeln!(w, "digraph tasks {{")?;
+    writeln!(w, "  node [style=filled, fontname=monospace];")?;
+    writeln!(
+        w,
+        "  root [label=\"seed {}\", shape=box, fillcolor=white];",
+        seed
+    )?;
+    for node in nodes {
+        writeln!(
+            w,
+            "  t{} [label=\"{}\\nseed {}\\nheight {}\\noutput {}\", fillcolor=\"{}\"];",
+            node.id,
+            dot_escape(node.typ.name()),
+            node.seed,
+            node.height,
+            node.output,
+            color(node.typ)
+        )?;
+        match node.parent {
+            Some(parent) => writeln!(w, "  t{} -> t{};", parent, node.id)?,
+            None => writeln!(w, "  root -> t{};", node.id)?,
+        }
+    }
+    writeln!(w, "}}")
+}
+
+/// Writes the tree as a JSON object with a flat list of tasks, each referring
+/// to its parent by id.
+pub fn write_json(
+    nodes: &[TreeNode],
+    seed: u64,
+    starting_height: usize,
+    max_children: usize,
+    mut w: impl Write,
+) -> io::Result<()> {
+    write!(
+        w,
+        "{{\"seed\":{},\"starting_height\":{},\"max_children\":{},\"tasks\":[",
+        seed, starting_height, max_children
+    )?;
+    for (idx, node) in nodes.iter().enumerate() {
+        if idx > 0 {
+            write!(w, ",")?;
+        }
+        write!(
+            w,
+            "{{\"id\":{},\"parent\":{},\"depth\":{},\"type\":{},\"seed\":{},\"height\":{},\"output\":{}}}",
+            node.id,
+            node.parent.map_or_else(|| "null".to_string(), |p| p.to_string()),
+            node.depth,
+            json_str(node.typ.name()),
+            node.seed,
+            node.height,
+            node.output
+        )?;
+    }
+    writeln!(w, "]}}")
+}
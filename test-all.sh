@@ -1,14 +1,12 @@
- #!/bin/bash
+#!/bin/bash
 
-for i in $(ls other_implementations/task_runner); do
-  echo ""
-  echo "========== ${i} =========="
-  cp other_implementations/task_runner/${i} src/main.rs 
-  cargo run -r 5664168989938163334 
-  # cargo run -r 1976915708242608314 
-  # cargo run -r 12605174704058567923
-  # cargo flamegraph -o flamegraphs/${i}.svg -- 5664168989938163334
-  # cargo flamegraph -o flamegraphs/${i}.svg -- 1976915708242608314 
-  # cargo flamegraph -o flamegraphs/${i}.svg -- 12605174704058567923 
-  # rm perf.data
-done
+# Runs every strategy on the seeds below and compares them, any extra
+# arguments are passed on to `taskrunner bench`.
+cargo run -r --all-features -- bench --seeds 5664168989938163334 --heights 5 --repetitions 1 "$@"
+
+# for i in threadpool-channel threadpool-mutex threadpool-try_channel tokio-async serial-dfs threadpool-dfs threadpool-recv work-stealing; do
+#   cargo flamegraph -o flamegraphs/${i}.svg -- --strategy ${i} 5664168989938163334
+#   cargo flamegraph -o flamegraphs/${i}.svg -- --strategy ${i} 1976915708242608314
+#   cargo flamegraph -o flamegraphs/${i}.svg -- --strategy ${i} 12605174704058567923
+#   rm perf.data
+# done
@@ -0,0 +1,477 @@
+//! Every scheduler must produce exactly the same `output,hash,derive,random`
+//! line as the original implementation for these trees.
+
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    net::TcpStream,
+    path::PathBuf,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use taskrunner::{
+    aggregate::{Aggregator, Tally},
+    cache::OutputCache,
+    cancel::CancelToken,
+    checkpoint::Checkpoint,
+    distributed::{self, Coordinator},
+    scheduler::{Checkpointing, ThreadPoolChannel, POLICIES},
+    task::{self, Task, TaskType},
+    tree, Scheduler, Strategy,
+};
+
+const THREADS: usize = 4;
+
+/// (seed, starting height, max. children, expected line), including the seeds
+/// of `test-all.sh` at heights small enough to keep the suite quick.
+const GOLDEN: &[(u64, usize, usize, &str)] = &[
+    (5664168989938163334, 1, 2, "4381307213667222830,11,10,14"),
+    (1976915708242608314, 0, 3, "3476838179903372363,6,8,4"),
+    (12605174704058567923, 1, 2, "2886732576250370127,9,12,5"),
+    (0, 2, 2, "2644719412173832808,6,16,6"),
+];
+
+fn check(name: &str, scheduler: &dyn Scheduler) {
+    for &(seed, height, max_children, expected) in GOLDEN {
+        let summary = scheduler.run(Task::generate_initial(seed, height, max_children));
+        assert_eq!(
+            summary.to_string(),
+            expected,
+            "{} on seed {}, height {}, max. children {}",
+            name,
+            seed,
+            height,
+            max_children
+        );
+    }
+}
+
+macro_rules! strategy_tests {
+    ($($(#[$attr:meta])* $test:ident => $strategy:ident,)*) => {
+        $(
+            $(#[$attr])*
+            #[test]
+            fn $test() {
+                check(Strategy::$strategy.name(), &*Strategy::$strategy.scheduler(THREADS));
+            }
+        )*
+    };
+}
+
+strategy_tests! {
+    threadpool_channel => ThreadPoolChannel,
+    threadpool_mutex => ThreadPoolMutex,
+    threadpool_try_channel => ThreadPoolTryChannel,
+    #[cfg(feature = "tokio")]
+    tokio_async => TokioAsync,
+    serial_dfs => SerialDfs,
+    threadpool_dfs => ThreadPoolDfs,
+    threadpool_recv => ThreadPoolRecv,
+    work_stealing => WorkStealing,
+    #[cfg(feature = "rayon")]
+    rayon_recursive => RayonRecursive,
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn tokio_async_without_threads() {
+    // Still runs the tasks one at a time rather than never.
+    check(
+        "tokio-async on 0 threads",
+        &taskrunner::scheduler::TokioAsync::new(0),
+    );
+}
+
+#[test]
+fn threadpool_channel_with_max_pending() {
+    for max_pending in [1, 8, 64] {
+        let name = format!("threadpool-channel with --max-pending {}", max_pending);
+        check(
+            &name,
+            &ThreadPoolChannel::new(THREADS).with_max_pending(max_pending),
+        );
+    }
+
+    // Any limit from about the height of the tree plus one on is kept.
+    let trees = GOLDEN
+        .iter()
+        .map(|&(seed, height, max_children, _)| (seed, height, max_children))
+        .chain([(5664168989938163334, 3, 5)]);
+    for (seed, height, max_children) in trees {
+        let initial = Task::generate_initial(seed, height, max_children);
+        for max_pending in [height + 1, 8, 20, 40] {
+            let summary = ThreadPoolChannel::new(THREADS)
+                .with_max_pending(max_pending)
+                .run(initial.clone());
+            assert!(
+                summary.peak_pending <= Some(max_pending),
+                "peak of {:?} above --max-pending {} on seed {}, height {}",
+                summary.peak_pending,
+                max_pending,
+                seed,
+                height
+            );
+        }
+    }
+}
+
+#[test]
+fn threadpool_channel_with_progress() {
+    check(
+        "threadpool-channel with progress",
+        &ThreadPoolChannel::new(THREADS).with_progress(Duration::from_millis(50)),
+    );
+}
+
+#[test]
+fn threadpool_channel_with_policies() {
+    for &policy in POLICIES {
+        let name = format!("threadpool-channel with --policy {}", policy);
+        check(&name, &ThreadPoolChannel::new(THREADS).with_policy(policy));
+    }
+}
+
+#[test]
+fn threadpool_channel_with_batching() {
+    check(
+        "threadpool-channel with batching",
+        &ThreadPoolChannel::new(THREADS).with_batching(),
+    );
+    check(
+        "threadpool-channel with batching and cancel",
+        &ThreadPoolChannel::new(THREADS)
+            .with_batching()
+            .with_cancel(CancelToken::new()),
+    );
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("taskrunner-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn checkpointing() {
+    let path = temp_path("golden.checkpoint");
+    // Checkpoints are written next to the path, not over files of its stem.
+    let neighbour = temp_path("golden.tmp");
+    std::fs::write(&neighbour, "not the checkpoint").unwrap();
+    let scheduler =
+        Checkpointing::new(THREADS, path.clone(), Duration::ZERO, Checkpoint::default());
+    check("checkpointing", &scheduler);
+    assert_eq!(
+        std::fs::read_to_string(&neighbour).unwrap(),
+        "not the checkpoint"
+    );
+    std::fs::remove_file(neighbour).unwrap();
+
+    // The final checkpoint has nothing left to do.
+    let checkpoint = Checkpoint::read_from(&path).unwrap();
+    assert!(checkpoint.pending.is_empty());
+    assert_eq!(checkpoint.summary().to_string(), GOLDEN[GOLDEN.len() - 1].3);
+
+    // A checkpoint of other task kinds is refused.
+    let contents = std::fs::read_to_string(&path).unwrap();
+    let fingerprint = format!("fingerprint {}\n", task::fingerprint());
+    let other = format!("fingerprint {}\n", !task::fingerprint());
+    std::fs::write(&path, contents.replacen(&fingerprint, &other, 1)).unwrap();
+    assert!(Checkpoint::read_from(&path).is_err());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn resuming_from_a_checkpoint() {
+    for &(seed, height, max_children, expected) in GOLDEN {
+        // Run the first few tasks by hand, as if the run had been interrupted.
+        let mut pending = Task::generate_initial(seed, height, max_children);
+        let mut checkpoint = Checkpoint {
+            seed,
+            starting_height: height,
+            max_children,
+            ..Default::default()
+        };
+        let mut count_map = HashMap::new();
+        for _ in 0..3.min(pending.len()) {
+            let task = pending.remove(0);
+            let (output, children) = task.execute();
+            checkpoint.output ^= output;
+            *count_map.entry(task.typ).or_insert(0usize) += 1;
+            pending.extend(children);
+        }
+        checkpoint.count_map = count_map;
+        checkpoint.pending = pending;
+
+        let path = temp_path(&format!("resume-{}.checkpoint", seed));
+        checkpoint.write_to(&path).unwrap();
+        let checkpoint = Checkpoint::read_from(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let mut summary = ThreadPoolChannel::new(THREADS).run(checkpoint.pending.clone());
+        summary.merge(&checkpoint.summary());
+        assert_eq!(summary.to_string(), expected, "resuming seed {}", seed);
+    }
+}
+
+#[test]
+fn distributed() {
+    let coordinator = Coordinator::bind("127.0.0.1:0").unwrap();
+    let addr = coordinator.local_addr().unwrap();
+
+    // Workers keep serving until the coordinator tells them the tree is done,
+    // so every golden tree gets its own set of them.
+    for &(seed, height, max_children, expected) in GOLDEN {
+        let workers: Vec<_> = (0..2)
+            .map(|_| thread::spawn(move || distributed::run_worker(&addr, 2).unwrap()))
+            .collect();
+        let summary = coordinator.run(Task::generate_initial(seed, height, max_children));
+        let executed: usize = workers.into_iter().map(|w| w.join().unwrap()).sum();
+
+        assert_eq!(
+            summary.to_string(),
+            expected,
+            "coordinator on seed {}",
+            seed
+        );
+        assert_eq!(executed, summary.total());
+    }
+}
+
+#[test]
+fn coordinator_without_workers() {
+    let coordinator = Coordinator::bind("127.0.0.1:0")
+        .unwrap()
+        .with_worker_timeout(Duration::from_millis(200));
+    let addr = coordinator.local_addr().unwrap();
+    let initial = Task::generate_initial(0, 2, 2);
+
+    let hello = move |fingerprint: u64| {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"TRN2").unwrap();
+        stream.write_all(&fingerprint.to_be_bytes()).unwrap();
+        stream.write_all(b"G").unwrap();
+        let mut reply = [0];
+        stream.read_exact(&mut reply).unwrap();
+        reply
+    };
+    let worker = thread::spawn(move || {
+        // A worker with other task kinds is turned away.
+        assert_eq!(&hello(!task::fingerprint()), b"K");
+        // A worker which takes a task and then goes away without a result.
+        assert_eq!(&hello(task::fingerprint()), b"T");
+    });
+    let summary = coordinator.run(initial.clone());
+    worker.join().unwrap();
+
+    assert_eq!(summary.unfinished, Some(initial.len()));
+    assert_eq!(summary.total(), 0);
+}
+
+#[test]
+fn exported_tree() {
+    for &(seed, height, max_children, expected) in GOLDEN {
+        let nodes = tree::explore(
+            Task::generate_initial(seed, height, max_children),
+            THREADS,
+            None,
+        );
+        let output = nodes.iter().fold(0, |output, node| output ^ node.output);
+        let total: usize = expected
+            .split(',')
+            .skip(1)
+            .map(|n| n.parse::<usize>().unwrap())
+            .sum();
+        assert_eq!(
+            output.to_string(),
+            expected.split(',').next().unwrap(),
+            "tree of seed {}",
+            seed
+        );
+        assert_eq!(nodes.len(), total, "tree of seed {}", seed);
+        for (id, node) in nodes.iter().enumerate() {
+            assert_eq!(node.id, id);
+            match node.parent {
+                Some(parent) => assert_eq!(nodes[parent].depth + 1, node.depth),
+                None => assert_eq!(node.depth, 0),
+            }
+        }
+        // The ids must not depend on the order the results came in.
+        let serial = tree::explore(Task::generate_initial(seed, height, max_children), 1, None);
+        assert_eq!(nodes, serial, "tree of seed {}", seed);
+
+        let limited = tree::explore(
+            Task::generate_initial(seed, height, max_children),
+            THREADS,
+            Some(0),
+        );
+        assert!(limited.iter().all(|node| node.parent.is_none()));
+    }
+}
+
+#[test]
+fn tree_statistics_with_cache() {
+    let path = temp_path("stats.cache");
+    for &(seed, height, max_children, expected) in GOLDEN {
+        // The second pass over the same tree, with the cache reopened from
+        // disk, must not execute anything.
+        for pass in 0..2 {
+            let cache = OutputCache::open(&path).unwrap();
+            let stats = tree::statistics(
+                Task::generate_initial(seed, height, max_children),
+                THREADS,
+                Some(&cache),
+            )
+            .unwrap();
+            let line = format!(
+                "{},{},{},{}",
+                stats.output,
+                stats.count(TaskType::Hash),
+                stats.count(TaskType::Derive),
+                stats.count(TaskType::Random)
+            );
+            assert_eq!(line, expected, "statistics of seed {}", seed);
+            assert_eq!(stats.levels.len(), height + 1);
+            if pass == 1 {
+                assert_eq!(
+                    stats.executed, 0,
+                    "statistics of seed {} from the cache",
+                    seed
+                );
+            }
+        }
+    }
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn threadpool_channel_with_cache() {
+    let path = temp_path("run.cache");
+    for pass in 0..2 {
+        let cache = Arc::new(OutputCache::open(&path).unwrap());
+        check(
+            "threadpool-channel with cache",
+            &ThreadPoolChannel::new(THREADS).with_cache(cache.clone()),
+        );
+        let stats = cache.stats();
+        if pass == 0 {
+            assert_eq!(stats.hits, 0);
+        } else {
+            assert_eq!(stats.misses, 0);
+        }
+    }
+    // Compacting keeps every output and leaves files of the same stem alone,
+    // and a cleared cache starts over.
+    let len = OutputCache::count(&path).unwrap();
+    let neighbour = temp_path("run.tmp");
+    std::fs::write(&neighbour, "not the cache").unwrap();
+    assert_eq!(OutputCache::compact(&path).unwrap(), len);
+    assert_eq!(OutputCache::open(&path).unwrap().len(), len);
+    assert_eq!(
+        std::fs::read_to_string(&neighbour).unwrap(),
+        "not the cache"
+    );
+    std::fs::remove_file(neighbour).unwrap();
+    OutputCache::clear(&path).unwrap();
+    assert!(OutputCache::open(&path).unwrap().is_empty());
+
+    // A cache of other task kinds is refused, and compacting leaves it alone.
+    let mut other = b"TRC2".to_vec();
+    other.extend((!task::fingerprint()).to_le_bytes());
+    other.extend([3; 17]);
+    std::fs::write(&path, &other).unwrap();
+    assert!(OutputCache::open(&path).is_err());
+    assert!(OutputCache::count(&path).is_err());
+    assert!(OutputCache::compact(&path).is_err());
+    assert_eq!(std::fs::read(&path).unwrap(), other);
+    std::fs::remove_file(path).unwrap();
+
+    // Counting the outputs never creates a cache.
+    let missing = temp_path("missing.cache");
+    assert!(OutputCache::count(&missing).is_err());
+    assert!(!missing.exists());
+}
+
+#[test]
+fn cancelling() {
+    // A token which is never cancelled changes nothing.
+    check(
+        "threadpool-channel with cancel",
+        &ThreadPoolChannel::new(THREADS).with_cancel(CancelToken::new()),
+    );
+
+    let cancel = CancelToken::new();
+    cancel.cancel();
+    let (seed, height, max_children, _) = GOLDEN[0];
+    let initial = Task::generate_initial(seed, height, max_children);
+    for scheduler in [
+        ThreadPoolChannel::new(THREADS),
+        ThreadPoolChannel::new(THREADS).with_batching(),
+    ] {
+        let summary = scheduler.with_cancel(cancel.clone()).run(initial.clone());
+        assert_eq!((summary.output, summary.total()), (0, 0));
+        assert_eq!(summary.unfinished, Some(initial.len()));
+    }
+}
+
+#[test]
+fn resuming_a_cancelled_checkpoint() {
+    for &(seed, height, max_children, expected) in GOLDEN {
+        // Wherever the run is stopped, the rest of the tree is in the checkpoint.
+        let path = temp_path(&format!("cancelled-{}.checkpoint", seed));
+        let base = Checkpoint {
+            seed,
+            starting_height: height,
+            max_children,
+            ..Default::default()
+        };
+        let cancel = CancelToken::new().with_deadline(Instant::now() + Duration::from_millis(50));
+        let scheduler = Checkpointing::new(THREADS, path.clone(), Duration::from_secs(60), base)
+            .with_cancel(cancel);
+        let first = scheduler.run(Task::generate_initial(seed, height, max_children));
+
+        let checkpoint = Checkpoint::read_from(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(first.unfinished.unwrap_or(0), checkpoint.pending.len());
+        let mut summary = ThreadPoolChannel::new(THREADS).run(checkpoint.pending.clone());
+        summary.merge(&checkpoint.summary());
+        assert_eq!(
+            summary.to_string(),
+            expected,
+            "resuming cancelled seed {}",
+            seed
+        );
+    }
+}
+
+#[test]
+fn aggregating_across_threads() {
+    for &(seed, height, max_children, expected) in GOLDEN {
+        let mut results = Vec::new();
+        let mut pending = Task::generate_initial(seed, height, max_children);
+        while let Some(task) = pending.pop() {
+            let (output, children) = task.execute();
+            results.push((task.typ, output));
+            pending.extend(children);
+        }
+
+        let mut tally = Tally::default();
+        for &(typ, output) in &results {
+            tally.record(typ, output);
+        }
+        assert_eq!(tally.summary().to_string(), expected);
+
+        // More threads than slots, so some of them share one.
+        let aggregator = Aggregator::new(THREADS);
+        thread::scope(|s| {
+            for chunk in results.chunks(results.len().div_ceil(2 * THREADS)) {
+                let aggregator = &aggregator;
+                s.spawn(move || {
+                    chunk
+                        .iter()
+                        .for_each(|&(typ, output)| aggregator.record(typ, output))
+                });
+            }
+        });
+        assert_eq!(aggregator.tally(), tally, "aggregated seed {}", seed);
+    }
+}
@@ -0,0 +1,190 @@
+//! Registering a `TaskKind` changes every tree generated afterwards in the
+//! whole process, so this runs apart from the golden trees.
+
+use std::{sync::OnceLock, time::Duration};
+
+use taskrunner::{
+    checkpoint::Checkpoint,
+    scheduler::{Checkpointing, Policy, SerialDfs, ThreadPoolChannel},
+    task::{self, RegisterError, Task, TaskKind, TaskType},
+    tree::{self, TreeNode},
+    Scheduler,
+};
+
+const THREADS: usize = 4;
+
+// A cheap stand-in for a user defined workload.
+struct Mix;
+
+impl TaskKind for Mix {
+    fn name(&self) -> &str {
+        "mix"
+    }
+
+    fn weight(&self) -> u32 {
+        2
+    }
+
+    fn execute(&self, seed: u64) -> u64 {
+        (seed ^ (seed >> 31)).wrapping_mul(0x9e3779b97f4a7c15)
+    }
+}
+
+/// The seed on which `Fragile` panics.
+const FRAGILE_SEED: u64 = 0xdead;
+
+// A workload with a bug, which only shows on one seed, and a name which has
+// to be quoted.
+struct Fragile;
+
+impl TaskKind for Fragile {
+    fn name(&self) -> &str {
+        "fragile\"v1\""
+    }
+
+    fn execute(&self, seed: u64) -> u64 {
+        assert_ne!(seed, FRAGILE_SEED, "fragile task hit its bad seed");
+        seed.rotate_left(17)
+    }
+}
+
+// Could never be drawn, so it is refused.
+struct Inert;
+
+impl TaskKind for Inert {
+    fn name(&self) -> &str {
+        "inert"
+    }
+
+    fn weight(&self) -> u32 {
+        0
+    }
+
+    fn execute(&self, seed: u64) -> u64 {
+        seed
+    }
+}
+
+/// Registers the kinds once, in the same order whichever test runs first.
+fn kinds() -> (TaskType, TaskType) {
+    static KINDS: OnceLock<(TaskType, TaskType)> = OnceLock::new();
+    *KINDS.get_or_init(|| {
+        (
+            task::register_kind(Mix).unwrap(),
+            task::register_kind(Fragile).unwrap(),
+        )
+    })
+}
+
+#[test]
+fn custom_kind() {
+    let (mix, fragile) = kinds();
+    assert_eq!(mix.name(), "mix");
+    assert_eq!(
+        TaskType::all(),
+        [
+            TaskType::Hash,
+            TaskType::Derive,
+            TaskType::Random,
+            mix,
+            fragile
+        ]
+    );
+    assert_eq!(
+        task::register_kind(Mix),
+        Err(RegisterError::DuplicateName("mix".to_string()))
+    );
+    assert_eq!(
+        task::register_kind(Inert),
+        Err(RegisterError::ZeroWeight("inert".to_string()))
+    );
+
+    let initial = Task::generate_initial(42, 2, 3);
+    let expected = SerialDfs.run(initial.clone());
+    assert!(expected.count(mix) > 0);
+    // output followed by the counts of hash, derive, random, mix and fragile
+    assert_eq!(expected.to_string().split(',').count(), 6);
+
+    for scheduler in [
+        ThreadPoolChannel::new(THREADS),
+        ThreadPoolChannel::new(THREADS).with_batching(),
+    ] {
+        assert_eq!(
+            scheduler.run(initial.clone()).to_string(),
+            expected.to_string()
+        );
+    }
+}
+
+#[test]
+fn panicking_task() {
+    let (_, fragile) = kinds();
+    let failing = Task {
+        typ: fragile,
+        seed: FRAGILE_SEED,
+        height: 1,
+        max_children: 2,
+    };
+    let mut initial = Task::generate_initial(42, 2, 3);
+    initial.push(failing.clone());
+
+    let schedulers = [
+        ThreadPoolChannel::new(THREADS),
+        ThreadPoolChannel::new(THREADS).with_policy(Policy::Lifo),
+        ThreadPoolChannel::new(THREADS).with_max_pending(8),
+        ThreadPoolChannel::new(THREADS).with_batching(),
+    ];
+    for scheduler in schedulers {
+        let summary = scheduler.with_retries(2).run(initial.clone());
+        let failure = summary.failure.expect("the run should fail");
+        assert_eq!(failure.task, failing);
+        assert_eq!(failure.attempts, 3);
+        assert!(summary.unfinished.unwrap() >= 1);
+    }
+
+    // Without retries, and the task is left for a resumed run.
+    let path = std::env::temp_dir().join(format!(
+        "taskrunner-{}-fragile.checkpoint",
+        std::process::id()
+    ));
+    let summary = Checkpointing::new(
+        THREADS,
+        path.clone(),
+        Duration::from_secs(60),
+        Checkpoint::default(),
+    )
+    .run(initial.clone());
+    let failure = summary.failure.expect("the run should fail");
+    assert_eq!((&failure.task, failure.attempts), (&failing, 1));
+    assert!(Checkpoint::read_from(&path)
+        .unwrap()
+        .pending
+        .contains(&failing));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn exported_names_are_escaped() {
+    let (_, fragile) = kinds();
+    let nodes = [TreeNode {
+        id: 0,
+        parent: None,
+        depth: 0,
+        typ: fragile,
+        seed: 1,
+        height: 0,
+        output: 2,
+    }];
+
+    let mut dot = Vec::new();
+    tree::write_dot(&nodes, 1, &mut dot).unwrap();
+    assert!(String::from_utf8(dot)
+        .unwrap()
+        .contains(r#"label="fragile\"v1\"\nseed 1"#));
+
+    let mut json = Vec::new();
+    tree::write_json(&nodes, 1, 0, 0, &mut json).unwrap();
+    assert!(String::from_utf8(json)
+        .unwrap()
+        .contains(r#""type":"fragile\"v1\"","#));
+}